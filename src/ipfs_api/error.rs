@@ -0,0 +1,104 @@
+use serde_json;
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    // The daemon could not be reached at the given API address
+    Connection(String, Box<dyn error::Error + Send + Sync>),
+    // The daemon answered with an error status. `message` and `code` are
+    // taken from the JSON error body it sends along, which looks like
+    // `{"Message":"...","Code":0,"Type":"error"}`
+    Http {
+        status: u16,
+        message: String,
+        code: Option<i64>,
+    },
+    // The daemon doesn't have (and couldn't find) the requested block or name
+    NotFound(String),
+    // The daemon didn't answer within the timeout, in seconds, it was given
+    Timeout(u64),
+    // A response that isn't what the API documents
+    Decode(String),
+    // An API address that isn't a supported multiaddr
+    InvalidAddress(String),
+    // The API file the daemon writes when it starts doesn't exist
+    NoApiFile(PathBuf),
+    NoHomeDir,
+    IoError(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Connection(ref addr, ref e) => {
+                write!(f, "IPFS daemon not reachable at {} ({})", addr, e)
+            }
+            Error::Http {
+                status,
+                ref message,
+                ..
+            } => write!(f, "{} (HTTP {})", message, status),
+            Error::NotFound(ref message) => write!(f, "not found: {}", message),
+            Error::Timeout(secs) => {
+                write!(f, "no answer within {} seconds", secs)
+            }
+            Error::Decode(ref message) => {
+                write!(f, "unexpected response: {}", message)
+            }
+            Error::InvalidAddress(ref addr) => {
+                write!(f, "invalid API address '{}'", addr)
+            }
+            Error::NoApiFile(ref path) => {
+                write!(f, "API file {} does not exist", path.display())
+            }
+            Error::NoHomeDir => write!(f, "could not determine home directory"),
+            Error::IoError(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Connection(_, ref e) => Some(&**e),
+            Error::IoError(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+
+// Builds the error for a response with a non-success status code. The daemon
+// reports missing blocks and unresolvable names as generic errors, so those
+// are recognized by their message.
+pub fn from_response(status: u16, body: &[u8]) -> Error {
+    let value = serde_json::from_slice::<serde_json::Value>(body).ok();
+    let message = value
+        .as_ref()
+        .and_then(|v| v.get("Message"))
+        .and_then(|m| m.as_str())
+        .map(|m| m.to_string())
+        .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_string());
+    let code = value
+        .as_ref()
+        .and_then(|v| v.get("Code"))
+        .and_then(|c| c.as_i64());
+
+    if message.contains("not found") || message.contains("could not resolve")
+    {
+        return Error::NotFound(message);
+    }
+    Error::Http {
+        status: status,
+        message: message,
+        code: code,
+    }
+}
@@ -0,0 +1,486 @@
+use home;
+use reqwest;
+use reqwest::header::{Authorization, Basic, Bearer, Headers};
+use serde_json;
+use url;
+use std::env;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub use self::error::Error;
+use self::multiaddr::Endpoint;
+
+mod error;
+mod multiaddr;
+#[cfg(unix)]
+mod unix;
+
+const IPFS_DIR_ENV_VAR: &'static str = "IPFS_PATH";
+const IPFS_DATA_FOLDER_NAME: &'static str = ".ipfs";
+const API_FILE_NAME: &'static str = "api";
+
+// How long the client waits for a response beyond the timeout the daemon is
+// given, so that the daemon's own (more informative) answer arrives first
+const TIMEOUT_GRACE_SECS: u64 = 5;
+// The delay before the first retry of a failed request, doubled for each
+// retry after it up to the maximum
+const RETRY_DELAY_MS: u64 = 500;
+const MAX_RETRY_DELAY_MS: u64 = 30_000;
+
+fn default_ipfs_dir() -> Result<PathBuf, Error> {
+    let mut home_dir = match home::home_dir() {
+        None => return Err(Error::NoHomeDir),
+        Some(p) => p,
+    };
+    home_dir.push(IPFS_DATA_FOLDER_NAME);
+    Ok(home_dir)
+}
+
+fn ipfs_data_dir_path() -> Result<PathBuf, Error> {
+    match env::var(IPFS_DIR_ENV_VAR) {
+        Ok(ref val) if val.len() > 0 => Ok(PathBuf::from(val)),
+        _ => default_ipfs_dir(),
+    }
+}
+
+// Credentials for API endpoints behind an authenticating reverse proxy
+pub enum Auth {
+    Bearer(String),
+    // A username and password
+    Basic(String, String),
+}
+
+impl Auth {
+    fn bearer(token: &str) -> Bearer {
+        Bearer {
+            token: token.to_string(),
+        }
+    }
+
+    fn basic(username: &str, password: &str) -> Basic {
+        Basic {
+            username: username.to_string(),
+            password: Some(password.to_string()),
+        }
+    }
+
+    // Sets the Authorization header carrying the credentials
+    fn set_header(&self, headers: &mut Headers) {
+        match *self {
+            Auth::Bearer(ref token) => {
+                headers.set(Authorization(Auth::bearer(token)))
+            }
+            Auth::Basic(ref username, ref password) => {
+                headers.set(Authorization(Auth::basic(username, password)))
+            }
+        }
+    }
+
+    // The value of the Authorization header, for requests that aren't sent
+    // with reqwest
+    fn header_value(&self) -> String {
+        match *self {
+            Auth::Bearer(ref token) => {
+                format!("{}", Authorization(Auth::bearer(token)))
+            }
+            Auth::Basic(ref username, ref password) => {
+                format!("{}", Authorization(Auth::basic(username, password)))
+            }
+        }
+    }
+}
+
+pub struct Shell {
+    transport: Transport,
+    addr: String, // The address the transport was made from
+    // The credentials sent with every request, if any
+    auth: Option<Auth>,
+    // How long the daemon may take to answer a request, if limited
+    timeout: Option<Duration>,
+    // How many times a request that failed for a transient reason is retried
+    retries: u32,
+}
+
+enum Transport {
+    // `url` is the base URL of the API endpoint
+    Http {
+        client: reqwest::Client,
+        url: String,
+    },
+    // The path of the socket the daemon listens on
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Shell {
+    // `addr` is the multiaddr of the API, or the URL of an endpoint such as
+    // `https://ipfs.example.com`
+    pub fn new(addr: &str) -> Result<Shell, Error> {
+        let is_url =
+            addr.starts_with("http://") || addr.starts_with("https://");
+        let transport = if is_url {
+            url::Url::parse(addr)
+                .map_err(|_| Error::InvalidAddress(addr.to_string()))?;
+            Transport::Http {
+                client: reqwest::Client::new(),
+                url: addr.trim_end_matches('/').to_string(),
+            }
+        } else {
+            match multiaddr::parse(addr)? {
+                Endpoint::Tcp { host, port, https } => {
+                    let scheme = if https { "https" } else { "http" };
+                    Transport::Http {
+                        client: reqwest::Client::new(),
+                        url: format!("{}://{}:{}", scheme, host, port),
+                    }
+                }
+                #[cfg(unix)]
+                Endpoint::Unix(path) => Transport::Unix(path),
+            }
+        };
+        Ok(Shell {
+            transport: transport,
+            addr: addr.to_string(),
+            auth: None,
+            timeout: None,
+            retries: 0,
+        })
+    }
+
+    pub fn new_local() -> Result<Shell, Error> {
+        let mut api_path = ipfs_data_dir_path()?;
+        api_path.push(API_FILE_NAME);
+
+        if !api_path.exists() {
+            return Err(Error::NoApiFile(api_path));
+        }
+
+        let mut api_file = File::open(api_path)?;
+
+        let mut addr = String::new();
+        api_file.read_to_string(&mut addr)?;
+
+        Shell::new(addr.trim())
+    }
+
+    // The base URL of the API, or None if it is reached over a Unix socket
+    pub fn url(&self) -> Option<&str> {
+        match self.transport {
+            Transport::Http { ref url, .. } => Some(url),
+            #[cfg(unix)]
+            Transport::Unix(_) => None,
+        }
+    }
+
+    // Whether requests can't be read on the way to the API, i.e. are sent
+    // over HTTPS or a local Unix socket
+    pub fn is_secure(&self) -> bool {
+        match self.transport {
+            Transport::Http { ref url, .. } => url.starts_with("https://"),
+            #[cfg(unix)]
+            Transport::Unix(_) => true,
+        }
+    }
+
+    // Send `auth` along with every request
+    pub fn set_auth(&mut self, auth: Auth) {
+        self.auth = Some(auth);
+    }
+
+    // Give up on requests that take longer than `timeout`. The daemon is told
+    // the timeout too, so that e.g. a `block/get` for a block no node
+    // provides fails instead of searching the network forever.
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<(), Error> {
+        let client_timeout = timeout + Duration::from_secs(TIMEOUT_GRACE_SECS);
+        let addr = &self.addr;
+        match self.transport {
+            Transport::Http { ref mut client, .. } => {
+                *client = reqwest::Client::builder()
+                    .timeout(client_timeout)
+                    .build()
+                    .map_err(|e| {
+                        Error::Connection(addr.clone(), Box::new(e))
+                    })?;
+            }
+            // the timeout is applied to each request on the socket instead
+            #[cfg(unix)]
+            Transport::Unix(_) => (),
+        }
+        self.timeout = Some(timeout);
+        Ok(())
+    }
+
+    // Retry requests that fail for a transient reason (the daemon or a proxy
+    // in front of it being unreachable or overloaded) up to `retries` times,
+    // backing off exponentially
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    // Put `data` onto the DAG, returning the CID of the resulting node. With
+    // `pin`, the node is pinned so that garbage collection keeps it.
+    pub fn dag_put(
+        &self,
+        data: &[u8],
+        input_enc: &str,
+        format: &str,
+        pin: bool,
+    ) -> Result<String, Error> {
+        let pin = if pin { "true" } else { "false" };
+        let params =
+            &[("input-enc", input_enc), ("format", format), ("pin", pin)];
+        let value = self.request_json("dag/put", params, Some(data))?;
+
+        // the response looks like `{"Cid":{"/":"<cid>"}}`
+        match value.pointer("/Cid/~1").and_then(|c| c.as_str()) {
+            Some(cid) => Ok(cid.to_string()),
+            None => Err(Error::Decode(format!("dag/put: {}", value))),
+        }
+    }
+
+    // Put a JSON document onto the DAG (stored as CBOR), returning the CID of
+    // the resulting node.
+    pub fn dag_put_json(&self, data: &[u8]) -> Result<String, Error> {
+        self.dag_put(data, "json", "cbor", false)
+    }
+
+    // Get a DAG node as JSON. `path` is a CID or an IPFS path.
+    pub fn dag_get(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let params = &[("arg", path)];
+        self.request_bytes("dag/get", params, None)
+    }
+
+    // Publish `path` under the IPNS name `key`, which may be the name of a
+    // key in the node's keystore or its peer ID.
+    pub fn name_publish(&self, path: &str, key: &str) -> Result<(), Error> {
+        let params = &[("arg", path), ("key", key)];
+        let value = self.request_json("name/publish", params, None)?;
+        match value.get("Name") {
+            Some(_) => Ok(()),
+            None => Err(Error::Decode(format!("name/publish: {}", value))),
+        }
+    }
+
+    // Resolve an IPNS name, returning the `/ipfs/...` path it points to
+    pub fn name_resolve(&self, name: &str) -> Result<String, Error> {
+        let params = &[("arg", name)];
+        let value = self.request_json("name/resolve", params, None)?;
+        match value.get("Path").and_then(|p| p.as_str()) {
+            Some(path) => Ok(path.to_string()),
+            None => Err(Error::Decode(format!("name/resolve: {}", value))),
+        }
+    }
+
+    pub fn block_get(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let params = &[("arg", path)];
+        self.request_bytes("block/get", params, None)
+    }
+
+    // Whether the node has the block `path` itself. Being offline, the
+    // request doesn't go looking for the block on the network.
+    pub fn has_block(&self, path: &str) -> Result<bool, Error> {
+        let params = &[("arg", path), ("offline", "true")];
+        match self.request_json("block/stat", params, None) {
+            Ok(_) => Ok(true),
+            Err(Error::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Pin `path` and everything it links to
+    pub fn pin_add(&self, path: &str) -> Result<(), Error> {
+        let params = &[("arg", path), ("recursive", "true")];
+        let value = self.request_json("pin/add", params, None)?;
+        match value.get("Pins") {
+            Some(_) => Ok(()),
+            None => Err(Error::Decode(format!("pin/add: {}", value))),
+        }
+    }
+
+    // Send a request for `command`, with `data` (if any) as the file argument,
+    // returning the response body if its status indicates success.
+    fn request_bytes(
+        &self,
+        command: &str,
+        params: &[(&str, &str)],
+        data: Option<&[u8]>,
+    ) -> Result<Vec<u8>, Error> {
+        let timeout_param;
+        let mut params = params.to_vec();
+        if let Some(timeout) = self.timeout {
+            timeout_param = format!("{}s", timeout.as_secs());
+            params.push(("timeout", &timeout_param));
+        }
+
+        let mut attempt = 0;
+        loop {
+            let started = Instant::now();
+            let result = self
+                .send(command, &params, data)
+                .map_err(|e| self.check_timeout(e, started));
+            match result {
+                Err(ref e) if attempt < self.retries && is_transient(e) => {
+                    let delay = retry_delay(attempt);
+                    debug!(
+                        "{} failed ({}), retrying in {}ms",
+                        command, e, delay
+                    );
+                    thread::sleep(Duration::from_millis(delay));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn send(
+        &self,
+        command: &str,
+        params: &[(&str, &str)],
+        data: Option<&[u8]>,
+    ) -> Result<Vec<u8>, Error> {
+        let (status, body) = match self.transport {
+            Transport::Http {
+                ref client,
+                ref url,
+            } => self.http_request(client, url, command, params, data)?,
+            #[cfg(unix)]
+            Transport::Unix(ref socket) => {
+                let query =
+                    url::form_urlencoded::Serializer::new(String::new())
+                        .extend_pairs(params)
+                        .finish();
+                let target = format!("/api/v0/{}?{}", command, query);
+                let authorization = self.auth.as_ref().map(Auth::header_value);
+                let client_timeout = self
+                    .timeout
+                    .map(|t| t + Duration::from_secs(TIMEOUT_GRACE_SECS));
+                unix::post(
+                    socket,
+                    &target,
+                    authorization.as_ref().map(|a| &a[..]),
+                    client_timeout,
+                    data,
+                )
+                .map_err(|e| {
+                    Error::Connection(self.addr.clone(), Box::new(e))
+                })?
+            }
+        };
+
+        if status < 200 || status >= 300 {
+            debug!("{} failed with status {}", command, status);
+            return Err(error::from_response(status, &body));
+        }
+        Ok(body)
+    }
+
+    // Turns the error of a request that ran out of time into a Timeout. That
+    // is either the daemon giving up at the deadline it was given, or the
+    // connection timing out because the daemon didn't answer at all.
+    fn check_timeout(&self, e: Error, started: Instant) -> Error {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return e,
+        };
+        let timed_out = match e {
+            Error::Http { ref message, .. } => {
+                message.contains("context deadline exceeded")
+            }
+            Error::Connection(..) => started.elapsed() >= timeout,
+            _ => false,
+        };
+        if timed_out {
+            Error::Timeout(timeout.as_secs())
+        } else {
+            e
+        }
+    }
+
+    fn http_request(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        command: &str,
+        params: &[(&str, &str)],
+        data: Option<&[u8]>,
+    ) -> Result<(u16, Vec<u8>), Error> {
+        use reqwest::multipart::{Form, Part};
+        use reqwest::header::TransferEncoding;
+
+        let request_url = self.make_request_url(url, command, params)?;
+
+        let mut req_builder = client.post(request_url);
+
+        if let Some(ref auth) = self.auth {
+            let mut headers = Headers::new();
+            auth.set_header(&mut headers);
+            req_builder.headers(headers);
+        }
+
+        if let Some(data) = data {
+            let part = Part::reader(Cursor::new(data.to_vec()))
+                .mime(reqwest::mime::APPLICATION_OCTET_STREAM);
+            let form = Form::new().part("", part);
+
+            req_builder.header(TransferEncoding::chunked());
+
+            req_builder.multipart(form);
+        }
+
+        let connection_error = |e: reqwest::Error| {
+            Error::Connection(self.addr.clone(), Box::new(e))
+        };
+        let mut resp = req_builder.send().map_err(&connection_error)?;
+
+        let mut body = Vec::new();
+        resp.copy_to(&mut body).map_err(&connection_error)?;
+        Ok((resp.status().as_u16(), body))
+    }
+
+    fn request_json(
+        &self,
+        command: &str,
+        params: &[(&str, &str)],
+        data: Option<&[u8]>,
+    ) -> Result<serde_json::Value, Error> {
+        let body = self.request_bytes(command, params, data)?;
+        serde_json::from_slice(&body)
+            .map_err(|e| Error::Decode(format!("{}: {}", command, e)))
+    }
+
+    fn make_request_url(
+        &self,
+        url: &str,
+        command: &str,
+        params: &[(&str, &str)],
+    ) -> Result<url::Url, Error> {
+        let base_url = format!("{}/api/v0/{}", url, command);
+        url::Url::parse_with_params(&base_url, params)
+            .map_err(|_| Error::InvalidAddress(self.addr.clone()))
+    }
+}
+
+// How long to wait before retry number `attempt` (counting from 0)
+fn retry_delay(attempt: u32) -> u64 {
+    1u64.checked_shl(attempt)
+        .map_or(MAX_RETRY_DELAY_MS, |factor| {
+            RETRY_DELAY_MS.saturating_mul(factor)
+        })
+        .min(MAX_RETRY_DELAY_MS)
+}
+
+// Whether a request that failed with `e` might succeed if it is retried
+fn is_transient(e: &Error) -> bool {
+    match *e {
+        Error::Connection(..) => true,
+        // errors a reverse proxy answers with when the daemon is down or busy
+        Error::Http { status, .. } => {
+            status == 502 || status == 503 || status == 504
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,168 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+#[cfg(unix)]
+use std::path::PathBuf;
+#[cfg(unix)]
+use url::percent_encoding::percent_decode;
+
+use super::Error;
+
+// Where the daemon's API can be reached, as described by the multiaddr the
+// daemon writes to its `api` file
+#[derive(Debug, PartialEq)]
+pub enum Endpoint {
+    // `host` is in the form it takes in a URL, i.e. IPv6 addresses are in
+    // brackets
+    Tcp {
+        host: String,
+        port: u16,
+        https: bool,
+    },
+    // A Unix domain socket the daemon serves HTTP on
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+// Parses an API multiaddr. Supported are
+//
+//   /ip4/<address>/tcp/<port>
+//   /ip6/<address>/tcp/<port>
+//   /dns/<name>/tcp/<port>   (and /dns4, /dns6)
+//   /unix/<path>             (on Unix only)
+//
+// where the TCP forms may end in `/http`, `/https` or `/tls/http`.
+pub fn parse(addr: &str) -> Result<Endpoint, Error> {
+    let invalid = || Error::InvalidAddress(addr.to_string());
+
+    if !addr.starts_with('/') {
+        return Err(invalid());
+    }
+
+    // a unix path is the rest of the multiaddr. Newer nodes percent-encode
+    // it into a single component, e.g. `/unix/%2Frun%2Fipfs.sock`. Elsewhere
+    // it is left to fail below like any other unknown protocol.
+    #[cfg(unix)]
+    {
+        if addr.starts_with("/unix/") {
+            let path = percent_decode(addr["/unix".len()..].as_bytes())
+                .decode_utf8()
+                .map_err(|_| invalid())?;
+            let path = match path.trim_end_matches('/') {
+                "" => return Err(invalid()),
+                p if p.starts_with("//") => p[1..].to_string(),
+                p => p.to_string(),
+            };
+            return Ok(Endpoint::Unix(PathBuf::from(path)));
+        }
+    }
+
+    let parts = addr[1..].split('/').collect::<Vec<_>>();
+    if parts.len() < 4 || parts[2] != "tcp" {
+        return Err(invalid());
+    }
+
+    let host = match (parts[0], parts[1]) {
+        ("ip4", ip) => {
+            ip.parse::<Ipv4Addr>().map_err(|_| invalid())?.to_string()
+        }
+        ("ip6", ip) => {
+            format!("[{}]", ip.parse::<Ipv6Addr>().map_err(|_| invalid())?)
+        }
+        ("dns", name) | ("dns4", name) | ("dns6", name) if !name.is_empty() => {
+            name.to_string()
+        }
+        _ => return Err(invalid()),
+    };
+    let port = parts[3].parse::<u16>().map_err(|_| invalid())?;
+    let https = match &parts[4..] {
+        [] | ["http"] => false,
+        ["https"] | ["tls", "http"] => true,
+        _ => return Err(invalid()),
+    };
+
+    Ok(Endpoint::Tcp {
+        host: host,
+        port: port,
+        https: https,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp(host: &str, port: u16, https: bool) -> Endpoint {
+        Endpoint::Tcp {
+            host: host.to_string(),
+            port: port,
+            https: https,
+        }
+    }
+
+    #[test]
+    fn tcp_addresses() {
+        assert_eq!(
+            parse("/ip4/127.0.0.1/tcp/5001").unwrap(),
+            tcp("127.0.0.1", 5001, false)
+        );
+        assert_eq!(
+            parse("/ip4/127.0.0.1/tcp/5001/http").unwrap(),
+            tcp("127.0.0.1", 5001, false)
+        );
+        assert_eq!(
+            parse("/ip6/::1/tcp/5001").unwrap(),
+            tcp("[::1]", 5001, false)
+        );
+        assert_eq!(
+            parse("/dns4/ipfs.example.com/tcp/443/https").unwrap(),
+            tcp("ipfs.example.com", 443, true)
+        );
+        assert_eq!(
+            parse("/dns/ipfs.example.com/tcp/443/tls/http").unwrap(),
+            tcp("ipfs.example.com", 443, true)
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn unix_addresses() {
+        assert_eq!(
+            parse("/unix/%2Frun%2Fipfs%2Fapi.sock").unwrap(),
+            Endpoint::Unix(PathBuf::from("/run/ipfs/api.sock"))
+        );
+        assert_eq!(
+            parse("/unix/run/ipfs.sock").unwrap(),
+            Endpoint::Unix(PathBuf::from("/run/ipfs.sock"))
+        );
+    }
+
+    #[test]
+    #[cfg(not(unix))]
+    fn unix_addresses() {
+        match parse("/unix/%2Frun%2Fipfs%2Fapi.sock") {
+            Err(Error::InvalidAddress(_)) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn invalid_addresses() {
+        for addr in &[
+            "",
+            "ip4/127.0.0.1/tcp/5001",
+            "/ip4/127.0.0.1",
+            "/ip4/256.0.0.1/tcp/5001",
+            "/ip4/127.0.0.1/udp/5001",
+            "/ip4/127.0.0.1/tcp/65536",
+            "/ip4/127.0.0.1/tcp/5001/ws",
+            "/ip6/127.0.0.1/tcp/5001",
+            "/dns//tcp/5001",
+            "/unix/",
+            "/unix/%2F",
+        ] {
+            match parse(addr) {
+                Err(Error::InvalidAddress(_)) => (),
+                result => panic!("{}: {:?}", addr, result),
+            }
+        }
+    }
+}
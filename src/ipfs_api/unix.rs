@@ -0,0 +1,176 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+// reqwest can only talk to TCP endpoints, so requests to a daemon listening
+// on a Unix domain socket are made with this minimal HTTP/1.1 client. The
+// API only needs POST requests, and `Connection: close` means the response
+// ends when the daemon closes the socket if it has no other framing.
+
+const BOUNDARY: &'static str = "ipgrv-multipart-boundary-7c1d0f58e1a4";
+
+// POST to `target` (the path and query of the request) over the socket at
+// `socket`, with `data` (if any) sent as the single file of a multipart
+// body. Returns the status code and the body of the response. `timeout`
+// limits how long any single read or write may block.
+pub fn post(
+    socket: &Path,
+    target: &str,
+    authorization: Option<&str>,
+    timeout: Option<Duration>,
+    data: Option<&[u8]>,
+) -> io::Result<(u16, Vec<u8>)> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+
+    let body = data.map(multipart_body).unwrap_or_else(Vec::new);
+    let mut head = format!(
+        "POST {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+         Content-Length: {}\r\n",
+        target,
+        body.len()
+    );
+    if let Some(authorization) = authorization {
+        head.push_str(&format!("Authorization: {}\r\n", authorization));
+    }
+    if data.is_some() {
+        head.push_str(&format!(
+            "Content-Type: multipart/form-data; boundary={}\r\n",
+            BOUNDARY
+        ));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&body)?;
+    stream.flush()?;
+
+    read_response(BufReader::new(stream))
+}
+
+fn multipart_body(data: &[u8]) -> Vec<u8> {
+    let mut body = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"file\"; \
+         filename=\"file\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+        BOUNDARY
+    ).into_bytes();
+    body.extend_from_slice(data);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+    body
+}
+
+fn read_response<R: BufRead>(mut reader: R) -> io::Result<(u16, Vec<u8>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    // e.g. "HTTP/1.1 200 OK"
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or_else(|| invalid_data(&format!("bad status line '{}'", line)))?;
+
+    let mut chunked = false;
+    let mut length = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("connection closed in headers"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let mut split = header.splitn(2, ':');
+        let name = split.next().unwrap_or("").trim().to_lowercase();
+        let value = split.next().unwrap_or("").trim();
+        if name == "transfer-encoding" {
+            chunked = value.to_lowercase().contains("chunked");
+        } else if name == "content-length" {
+            length = Some(value.parse::<usize>().map_err(|_| {
+                invalid_data(&format!("bad content length '{}'", value))
+            })?);
+        }
+    }
+
+    let mut body = Vec::new();
+    if chunked {
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            // the size may be followed by chunk extensions after a ';'
+            let size = line.split(';').next().unwrap_or("").trim();
+            let size = usize::from_str_radix(size, 16).map_err(|_| {
+                invalid_data(&format!("bad chunk size '{}'", size))
+            })?;
+            if size == 0 {
+                break;
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..])?;
+            // the CRLF that ends the chunk
+            line.clear();
+            reader.read_line(&mut line)?;
+        }
+    } else if let Some(length) = length {
+        body.resize(length, 0);
+        reader.read_exact(&mut body)?;
+    } else {
+        reader.read_to_end(&mut body)?;
+    }
+    Ok((status, body))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn parse(response: &str) -> io::Result<(u16, Vec<u8>)> {
+        read_response(Cursor::new(response.as_bytes().to_vec()))
+    }
+
+    #[test]
+    fn content_length_body() {
+        let response = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+        assert_eq!(parse(response).unwrap(), (200, b"hello".to_vec()));
+    }
+
+    #[test]
+    fn chunked_body() {
+        let response = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                        5\r\nhello\r\n6;name=value\r\n world\r\n0\r\n\r\n";
+        assert_eq!(parse(response).unwrap(), (200, b"hello world".to_vec()));
+    }
+
+    #[test]
+    fn body_read_to_eof() {
+        let response = "HTTP/1.1 500 Internal Server Error\r\n\
+                        Content-Type: text/plain\r\n\r\nno such command";
+        assert_eq!(
+            parse(response).unwrap(),
+            (500, b"no such command".to_vec())
+        );
+    }
+
+    #[test]
+    fn invalid_responses() {
+        for response in &[
+            "",
+            "HTTP/1.1 OK\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: five\r\n\r\nhello",
+            "HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nhello",
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nx\r\n",
+        ] {
+            assert!(parse(response).is_err(), "{:?}", response);
+        }
+    }
+}
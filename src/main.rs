@@ -1,4 +1,6 @@
+extern crate bs58;
 extern crate chrono;
+extern crate cid;
 extern crate fern;
 extern crate flate2;
 extern crate git2;
@@ -10,6 +12,7 @@ extern crate lmdb_zero as lmdb;
 extern crate log;
 extern crate multihash;
 extern crate reqwest;
+extern crate serde_json;
 extern crate url;
 
 use remote::Remote;
@@ -39,23 +42,27 @@ fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() != 3 {
         eprintln!("Usage: git-remote-ipgrv <remote> <url>");
-        process::exit(1);
+        process::exit(2);
     }
 
     if let Err(e) = setup_logger() {
-        eprintln!("Error setting up logger: {:?}", e);
+        eprintln!("Error setting up logger: {}", e);
         process::exit(1);
     }
     debug!("{:?}", args);
 
-    if let Err(e) = run(args.into_iter().nth(2).unwrap()) {
-        eprintln!("Error running helper: {:?}", e);
-        process::exit(1);
+    if let Err(e) = run(&args[1], &args[2]) {
+        debug!("error: {:?}", e);
+        eprintln!("fatal: {}", e);
+        if let Some(hint) = e.hint() {
+            eprintln!("hint: {}", hint);
+        }
+        process::exit(e.exit_code());
     }
 }
 
-fn run(remote_hash: String) -> Result<(), remote::Error> {
-    let mut remote = Remote::new()?;
-    let handler = remote::Handler::new(remote_hash);
+fn run(name: &str, url: &str) -> Result<(), remote::Error> {
+    let handler = remote::Handler::new(name, url)?;
+    let mut remote = Remote::new(&handler)?;
     remote.process_commands(&handler)
 }
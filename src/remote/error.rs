@@ -1,9 +1,13 @@
+use cid;
 use git2;
 use hex;
 use ipld_git;
 use lmdb;
 use multihash;
+use serde_json;
 use std::env;
+use std::error;
+use std::fmt;
 use std::io;
 
 use ipfs_api;
@@ -18,7 +22,189 @@ pub enum Error {
     LmdbError(lmdb::Error),
     IpldGitError(ipld_git::Error),
     MultihashError(multihash::Error),
+    CidError(cid::Error),
     InvalidCommand(String),
+    // A git config setting with a value that can't be used
+    InvalidConfig(String),
+    InvalidRoot(String),
+    InvalidUrl(String),
+    // The CID the daemon stored an object under, differing from the one it
+    // was expected to have: (expected, actual)
+    CidMismatch(String, String),
+    // A fetched object whose content doesn't hash to the requested object:
+    // (expected hash, CID it was fetched under, actual hash)
+    CorruptObject(String, String, String),
+    // A git object no node could provide in time: (hash, timeout in seconds)
+    ObjectNotFound(String, u64),
+    // A name that doesn't point to a repository root (yet)
+    NameNotFound(String),
+    // A ref update refused by the remote, with git's reason for it
+    PushRejected(String),
+    JsonError(serde_json::Error),
+}
+
+impl Error {
+    // The exit code of the helper when it fails with this error, so that
+    // scripts can tell failures apart:
+    //
+    //   1  anything not covered below
+    //   2  usage errors, including invalid remote URLs and API addresses
+    //   3  the IPFS daemon can't be found or reached
+    //   4  an object or name can't be found on IPFS
+    //   5  data from IPFS failed an integrity check
+    //   6  a problem with the local repository or tracker
+    //   7  git sent something the helper doesn't understand
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::InvalidUrl(_)
+            | Error::InvalidConfig(_)
+            | Error::EnvVarError(_)
+            | Error::ApiError(ipfs_api::Error::InvalidAddress(_)) => 2,
+            Error::ApiError(ipfs_api::Error::NotFound(_))
+            | Error::ApiError(ipfs_api::Error::Timeout(_))
+            | Error::ObjectNotFound(..)
+            | Error::NameNotFound(_) => 4,
+            Error::ApiError(ipfs_api::Error::Http { .. })
+            | Error::ApiError(ipfs_api::Error::Decode(_)) => 1,
+            Error::ApiError(_) => 3,
+            Error::CidMismatch(..)
+            | Error::CorruptObject(..)
+            | Error::InvalidRoot(_) => 5,
+            Error::Git2Error(_) | Error::LmdbError(_) | Error::IoError(_) => 6,
+            Error::InvalidCommand(_) => 7,
+            _ => 1,
+        }
+    }
+
+    // Advice on what to do about the error, if there is any to give
+    pub fn hint(&self) -> Option<&'static str> {
+        match *self {
+            Error::ApiError(ipfs_api::Error::Connection(..)) => {
+                Some("is `ipfs daemon` running?")
+            }
+            Error::ApiError(ipfs_api::Error::NoApiFile(_))
+            | Error::ApiError(ipfs_api::Error::NoHomeDir) => Some(
+                "start the daemon with `ipfs daemon`, point IPFS_PATH at the \
+                 repository of a running one, or set the API address with \
+                 `git config remote.<name>.ipgrvApi` or IPGRV_API",
+            ),
+            Error::ApiError(ipfs_api::Error::Http { status: 401, .. })
+            | Error::ApiError(ipfs_api::Error::Http { status: 403, .. }) => {
+                Some(
+                    "set a token with `git config remote.<name>.ipgrvApiToken` \
+                     or store credentials for the endpoint with a git \
+                     credential helper",
+                )
+            }
+            Error::ApiError(ipfs_api::Error::NotFound(_)) => Some(
+                "the object may not be available on the network; make sure a \
+                 node that has it is online",
+            ),
+            Error::NameNotFound(_) => Some(
+                "a name points to a repository once it has been pushed to",
+            ),
+            Error::ApiError(ipfs_api::Error::Timeout(_))
+            | Error::ObjectNotFound(..) => Some(
+                "make sure a node that has the data is online, or raise the \
+                 timeout with `git config remote.<name>.ipgrvTimeout <secs>`",
+            ),
+            Error::InvalidUrl(_) => Some(
+                "remote URLs look like ipgrv::<cid>, ipgrv::/ipns/<name>, \
+                 ipgrv::<domain> or ipgrv::<git hash>",
+            ),
+            Error::EnvVarError(_) => Some(
+                "git-remote-ipgrv is run by git, e.g. `git clone ipgrv::<cid>`",
+            ),
+            Error::CidMismatch(..) | Error::CorruptObject(..) => Some(
+                "the IPFS node may be misconfigured, or serving bad data",
+            ),
+            _ => None,
+        }
+    }
+
+    // A single-line description of why a push failed, used in the `error`
+    // line reported back to git for a ref
+    pub fn push_reason(&self) -> String {
+        let reason = match *self {
+            Error::PushRejected(ref reason) => reason.clone(),
+            ref e => e.to_string(),
+        };
+        reason.replace('\n', " ")
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::ApiError(ref e) => write!(f, "IPFS API error: {}", e),
+            Error::EnvVarError(ref e) => {
+                write!(f, "GIT_DIR is not set properly: {}", e)
+            }
+            Error::FromHexError(ref e) => write!(f, "invalid hash: {}", e),
+            Error::Git2Error(ref e)
+                if e.code() == git2::ErrorCode::NotFound =>
+            {
+                write!(f, "missing object: {}", e.message())
+            }
+            Error::Git2Error(ref e) => write!(f, "git error: {}", e.message()),
+            Error::IoError(ref e) => write!(f, "I/O error: {}", e),
+            Error::LmdbError(ref e) => write!(f, "tracker error: {}", e),
+            Error::IpldGitError(ref e) => {
+                write!(f, "invalid git object: {:?}", e)
+            }
+            Error::MultihashError(ref e) => write!(f, "multihash error: {}", e),
+            Error::CidError(ref e) => write!(f, "CID error: {}", e),
+            Error::InvalidCommand(ref command) => {
+                write!(f, "unexpected command from git: '{}'", command)
+            }
+            Error::InvalidConfig(ref reason) => {
+                write!(f, "invalid config: {}", reason)
+            }
+            Error::InvalidRoot(ref reason) => {
+                write!(f, "invalid repository root: {}", reason)
+            }
+            Error::InvalidUrl(ref reason) => {
+                write!(f, "invalid remote URL {}", reason)
+            }
+            Error::CidMismatch(ref expected, ref actual) => write!(
+                f,
+                "object stored as {} instead of {}",
+                actual, expected
+            ),
+            Error::CorruptObject(ref expected, ref cid, ref actual) => write!(
+                f,
+                "object {} (CID {}) is corrupt: it hashes to {}",
+                expected, cid, actual
+            ),
+            Error::ObjectNotFound(ref hash, secs) => write!(
+                f,
+                "object {} not found on the network within {} seconds",
+                hash, secs
+            ),
+            Error::NameNotFound(ref name) => {
+                write!(f, "name {} does not point to a repository root", name)
+            }
+            Error::PushRejected(ref reason) => {
+                write!(f, "push rejected: {}", reason)
+            }
+            Error::JsonError(ref e) => write!(f, "invalid JSON: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::ApiError(ref e) => Some(e),
+            Error::EnvVarError(ref e) => Some(e),
+            Error::FromHexError(ref e) => Some(e),
+            Error::Git2Error(ref e) => Some(e),
+            Error::IoError(ref e) => Some(e),
+            Error::LmdbError(ref e) => Some(e),
+            Error::JsonError(ref e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 impl From<env::VarError> for Error {
@@ -56,3 +242,15 @@ impl From<multihash::Error> for Error {
         Error::MultihashError(e)
     }
 }
+
+impl From<cid::Error> for Error {
+    fn from(e: cid::Error) -> Self {
+        Error::CidError(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::JsonError(e)
+    }
+}
@@ -1,81 +1,183 @@
 use flate2;
 use flate2::write::ZlibEncoder;
+use git2;
 use hex;
 use ipld_git;
 use multihash;
-use std::collections::VecDeque;
-use std::env;
-use std::fs::{create_dir_all, File};
+use std::collections::{HashSet, VecDeque};
+use std::fs::{create_dir_all, rename, File};
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::process;
 
-use ipfs_api;
 use super::Error;
+use super::options::Options;
+use super::store::{self, BlockStore};
 use super::tracker;
 
 pub struct FetchHelper<'a> {
-    queue: VecDeque<String>,
+    // Hex hashes of the objects to fetch, each with whether it was reached
+    // from a commit no local ref leads to
+    queue: VecDeque<(String, bool)>,
+    repo: &'a git2::Repository,
     tracker: &'a tracker::Tracker,
+    store: &'a dyn BlockStore,
+    options: &'a Options,
 }
 
 impl<'a> FetchHelper<'a> {
-    pub fn new(tracker: &'a tracker::Tracker) -> FetchHelper {
+    pub fn new(
+        repo: &'a git2::Repository,
+        tracker: &'a tracker::Tracker,
+        store: &'a dyn BlockStore,
+        options: &'a Options,
+    ) -> FetchHelper<'a> {
         FetchHelper {
             queue: VecDeque::new(),
+            repo: repo,
             tracker: tracker,
+            store: store,
+            options: options,
         }
     }
 
     // `hash` is a hex representation of the hash being fetched
     pub fn fetch(&mut self, hash: String) -> Result<(), Error> {
-        self.queue.push_back(hash);
+        self.queue.push_back((hash, false));
         self.fetch_queue()
     }
 
-    // fetch each of the objects in the queue from IPFS
+    // fetch each of the objects in the queue from IPFS. Objects the local
+    // repository already has (loose or packed) aren't fetched, and the walk
+    // stops at commits that are reachable from a local ref, since everything
+    // they link to is present as well. A commit no ref leads to may be what
+    // an interrupted fetch left behind without all of its history and
+    // trees, so the commits and trees below it are walked even if present.
     fn fetch_queue(&mut self) -> Result<(), Error> {
-        let api = ipfs_api::Shell::new_local().map_err(Error::ApiError)?;
-        while let Some(hash) = self.queue.pop_front() {
+        let odb = self.repo.odb()?;
+        let local_tips = self.local_tips()?;
+        let mut seen = HashSet::new();
+        let mut fetched = 0;
+        while let Some((hash, unreachable)) = self.queue.pop_front() {
             debug!("    fetching hash = {}", hash);
 
-            let mut base_dir = PathBuf::from(env::var("GIT_DIR")?);
-            base_dir.push("objects");
-            let obj_file = prepare_object_path(&mut base_dir, &hash)?;
+            let hash_bytes = hex::decode(&hash)?;
+            let oid = git2::Oid::from_bytes(&hash_bytes)?;
+            if !seen.insert(oid) {
+                continue;
+            }
 
-            if obj_file.exists() {
+            if odb.exists(oid) {
+                let (_, kind) = odb.read_header(oid)?;
+                let walk = match kind {
+                    git2::ObjectType::Commit => {
+                        !self.is_reachable(oid, &local_tips)
+                    }
+                    git2::ObjectType::Tree => unreachable,
+                    _ => false,
+                };
+                if !walk {
+                    debug!("    already have this oid, skipping");
+                    continue;
+                }
+                let obj_bytes = super::read_raw_object(&odb, oid)?;
+                self.enqueue_links(&obj_bytes, true)?;
                 continue;
             }
 
-            let hash_bytes = hex::decode(hash)?;
-            let obj_cid = ipld_git::util::sha1_to_cid(&hash_bytes)
-                .map_err(Error::IpldGitError)?;
-            debug!("    the corresponding cid = {}", obj_cid.to_string());
-            let obj_bytes = api.block_get(&obj_cid.to_string())
-                .map_err(Error::ApiError)?;
+            let mut base_dir = self.repo.path().to_path_buf();
+            base_dir.push("objects");
+            let obj_file = prepare_object_path(&mut base_dir, &hash)?;
+
+            let obj_bytes = self.store.get_object(oid)?;
+
+            // don't trust the node to have sent what we asked for
+            let obj_digest =
+                multihash::encode(multihash::Hash::SHA1, &obj_bytes)?;
+            let obj_hash = multihash::decode(&obj_digest)?.digest;
+            if obj_hash != &hash_bytes[..] {
+                return Err(Error::CorruptObject(
+                    oid.to_string(),
+                    store::object_cid(oid)?,
+                    hex::encode(obj_hash),
+                ));
+            }
 
             // add all linked objects to the queue to be fetched next
-            self.enqueue_links(&obj_bytes)?;
+            self.enqueue_links(&obj_bytes, false)?;
 
             let mut enc =
                 ZlibEncoder::new(Vec::new(), flate2::Compression::default());
-            enc.write(&obj_bytes)?;
+            enc.write_all(&obj_bytes)?;
             let compressed_bytes = enc.finish()?;
 
-            let mut f = File::create(obj_file)?;
-            f.write(&compressed_bytes)?;
+            // write to a temporary file first, so that an interrupted fetch
+            // doesn't leave a truncated object behind
+            let tmp_file = obj_file.with_file_name(format!(
+                "tmp_obj_{}_{}",
+                &hash[2..],
+                process::id()
+            ));
+            {
+                let mut f = File::create(&tmp_file)?;
+                f.write_all(&compressed_bytes)?;
+            }
+            rename(&tmp_file, obj_file)?;
 
             self.tracker.add_entry(&hash_bytes)?;
+
+            fetched += 1;
+            if self.options.verbosity > 1 {
+                eprintln!("Fetched {}", hex::encode(&hash_bytes));
+            } else if self.options.progress {
+                eprint!("\rFetching objects: {}", fetched);
+            }
+        }
+        if self.options.progress && self.options.verbosity <= 1 && fetched > 0
+        {
+            eprintln!("\rFetching objects: {}, done.", fetched);
         }
         Ok(())
     }
 
-    fn enqueue_links(&mut self, obj_bytes: &[u8]) -> Result<(), Error> {
+    // The commits local refs point to, directly or through tags
+    fn local_tips(&self) -> Result<Vec<git2::Oid>, Error> {
+        let mut tips = Vec::new();
+        for reference in self.repo.references()? {
+            let resolved = reference?.resolve().ok();
+            let target = match resolved.and_then(|r| r.target()) {
+                Some(target) => target,
+                None => continue,
+            };
+            let commit = self.repo
+                .find_object(target, None)
+                .and_then(|o| o.peel(git2::ObjectType::Commit));
+            if let Ok(commit) = commit {
+                tips.push(commit.id());
+            }
+        }
+        Ok(tips)
+    }
+
+    fn is_reachable(&self, oid: git2::Oid, tips: &[git2::Oid]) -> bool {
+        tips.iter().any(|&tip| {
+            tip == oid
+                || self.repo.graph_descendant_of(tip, oid).unwrap_or(false)
+        })
+    }
+
+    fn enqueue_links(
+        &mut self,
+        obj_bytes: &[u8],
+        unreachable: bool,
+    ) -> Result<(), Error> {
         let node =
             ipld_git::parse_object(obj_bytes).map_err(Error::IpldGitError)?;
 
         for link in node.links() {
             let link_multihash = multihash::decode(&link.cid.hash)?;
-            self.queue.push_back(hex::encode(link_multihash.digest))
+            self.queue
+                .push_back((hex::encode(link_multihash.digest), unreachable))
         }
         Ok(())
     }
@@ -96,3 +198,60 @@ fn prepare_object_path<'a>(
     path.push(&hash[2..]);
     Ok(path.as_path())
 }
+
+#[cfg(test)]
+mod tests {
+    use remote::push::PushHelper;
+    use remote::store::FsStore;
+    use remote::testing::{self, TempDir};
+    use super::*;
+
+    #[test]
+    fn fetches_history() {
+        let dir = TempDir::new("fetch-history");
+        let origin = testing::init_repo(&dir, "origin");
+        let store = FsStore::new(&dir.path().join("store"));
+        let options = Options::new();
+        testing::commit(&origin, "README", "hello\n");
+        let head = testing::commit(&origin, "NEWS", "news\n");
+        let origin_tracker = testing::tracker(&dir, "origin");
+        PushHelper::new(&origin, &origin_tracker, &store, &options)
+            .push(head)
+            .unwrap();
+
+        let clone = testing::init_repo(&dir, "clone");
+        let tracker = testing::tracker(&dir, "clone");
+        FetchHelper::new(&clone, &tracker, &store, &options)
+            .fetch(head.to_string())
+            .unwrap();
+
+        let commit = clone.find_commit(head).unwrap();
+        let parent = commit.parent(0).unwrap();
+        assert!(parent.tree().unwrap().get_name("README").is_some());
+        assert!(commit.tree().unwrap().get_name("NEWS").is_some());
+        assert!(tracker.has_entry(head.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn rejects_corrupt_objects() {
+        let dir = TempDir::new("fetch-corrupt");
+        let store = FsStore::new(&dir.path().join("store"));
+        let oid =
+            git2::Oid::from_str("e69de29bb2d1d6434b8b29ae775ad8c2e48c5391")
+                .unwrap();
+        store.put_object(oid, b"blob 3\0bad").unwrap();
+
+        let repo = testing::init_repo(&dir, "repo");
+        let tracker = testing::tracker(&dir, "repo");
+        let options = Options::new();
+        let result = FetchHelper::new(&repo, &tracker, &store, &options)
+            .fetch(oid.to_string());
+        match result {
+            Err(Error::CorruptObject(ref hash, _, _)) => {
+                assert_eq!(*hash, oid.to_string())
+            }
+            result => panic!("{:?}", result),
+        }
+        assert!(!repo.odb().unwrap().exists(oid));
+    }
+}
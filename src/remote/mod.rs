@@ -1,25 +1,52 @@
 use git2;
-use hex;
 use ipld_git;
+use std::convert::TryFrom;
 use std::env;
 use std::fs;
 use std::io;
+use std::path::Path;
+use std::time::Duration;
 
+use ipfs_api;
 pub use self::error::Error;
 
 mod error;
 mod fetch;
+mod options;
 mod push;
+mod root;
+mod store;
+#[cfg(test)]
+mod testing;
 mod tracker;
+mod url;
+
+pub use self::url::Target;
+
+// Environment variable naming the multiaddr of the IPFS API to use
+const API_ENV_VAR: &'static str = "IPGRV_API";
+// Prefix of store addresses that name a local directory
+const FS_STORE_SCHEME: &'static str = "file://";
+// Environment variable holding a bearer token for the IPFS API
+const API_TOKEN_ENV_VAR: &'static str = "IPGRV_API_TOKEN";
+// Defaults for `remote.<name>.ipgrvTimeout` (in seconds, 0 for none) and
+// `remote.<name>.ipgrvRetries`
+const DEFAULT_API_TIMEOUT: u64 = 120;
+const DEFAULT_API_RETRIES: u32 = 3;
+// The most retries `remote.<name>.ipgrvRetries` may ask for
+const MAX_API_RETRIES: u32 = 100;
 
 fn log_and_print(s: &str) {
     debug!("git <- '{}'", s);
     println!("{}", s);
 }
 
-enum Command {
-    Push(PushArgs),
-    Fetch(FetchArgs),
+// The batch of commands being collected. Pushes and fetches come in separate
+// batches, each terminated by a blank line.
+enum Batch {
+    Empty,
+    Push(Vec<PushArgs>),
+    Fetch(Vec<FetchArgs>),
 }
 
 struct PushArgs {
@@ -36,10 +63,14 @@ struct FetchArgs {
 pub struct Remote {
     repo: git2::Repository,
     tracker: tracker::Tracker,
+    store: Box<dyn store::BlockStore>,
+    // The repository root being updated by this session, loaded on first use
+    root: Option<root::RepoRoot>,
+    options: options::Options,
 }
 
 impl Remote {
-    pub fn new() -> Result<Remote, Error> {
+    pub fn new(handler: &Handler) -> Result<Remote, Error> {
         let repo = git2::Repository::open_from_env()?;
 
         let mut db_path = env::var("GIT_DIR")?;
@@ -47,90 +78,239 @@ impl Remote {
         db_path.push_str("/ipgrv");
         fs::create_dir_all(&db_path)?;
         debug!("Remote::new(), db_path = {}", &db_path);
-        let tracker = tracker::Tracker::new(&db_path)?;
+        let tracker = tracker::Tracker::new(&db_path, handler.name())?;
+        let store = open_store(&repo, handler)?;
 
         Ok(Remote {
             repo: repo,
             tracker: tracker,
+            store: store,
+            root: None,
+            options: options::Options::new(),
         })
     }
 
-    fn list(&self, handler: &Handler) -> Result<Vec<String>, Error> {
-        let mut refs = Vec::new();
-        let local_branches = self.repo.branches(Some(git2::BranchType::Local))?;
-        for branch_result in local_branches {
-            let (branch, _) = branch_result?;
-
-            let ref_name =
-                branch.get().name().expect("Branch name is not utf-8");
-
-            let ref_value = match self.tracker.get_ref(ref_name)? {
-                Some(val) => val,
-                None => String::from("?"),
+    // Returns the repository root for this session. If the remote URL names a
+    // previously published root, it is fetched from IPFS, otherwise we start
+    // from an empty root. An IPNS name that has not been published yet can
+    // only be pushed to, so `for_push` allows it to start out empty. A root
+    // CID can't be updated in place, so once we have pushed to one, we build
+    // on the root we published last rather than on the one in the URL.
+    fn root(
+        &mut self,
+        handler: &Handler,
+        for_push: bool,
+    ) -> Result<&mut root::RepoRoot, Error> {
+        if self.root.is_none() {
+            let root = match *handler.target() {
+                Target::Root(ref cid) => {
+                    match self.tracker.get_ref(&published_root_key(cid))? {
+                        Some(ref published) => self.get_root(published)?,
+                        None => self.get_root(cid)?,
+                    }
+                }
+                Target::Ipns(ref name) | Target::DnsLink(ref name) => {
+                    match self.store.resolve_name(name) {
+                        Ok(path) => self.get_root(&path)?,
+                        Err(Error::NameNotFound(_)) if for_push => {
+                            root::RepoRoot::new()
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                Target::GitHash(ref hash) => self.tracked_root(hash)?,
             };
+            self.root = Some(root);
+        }
+        Ok(self.root.as_mut().unwrap())
+    }
 
-            refs.push(format!("{} {}", ref_value, ref_name));
+    // Remotes addressed by a commit hash don't have a published root to start
+    // from, so use the refs recorded by the tracker the last time we talked to
+    // this remote. Failing that, the hash is taken to be `refs/heads/master`.
+    fn tracked_root(&self, hash: &str) -> Result<root::RepoRoot, Error> {
+        let mut root = root::RepoRoot::new();
+        // when cloning, there is nothing in the tracker yet
+        if !self.options.cloning {
+            for (name, hash) in self.tracker.refs()? {
+                let peeled = match git2::Oid::from_str(&hash) {
+                    Ok(oid) => self.peel_tag(oid)?.map(|p| p.to_string()),
+                    Err(_) => None,
+                };
+                root.set_ref(&name, &hash, peeled);
+            }
+            root.head = self.tracker.get_ref("HEAD")?;
+        }
+        if root.refs.is_empty() {
+            root.refs
+                .insert("refs/heads/master".to_string(), hash.to_string());
+            root.head = Some("refs/heads/master".to_string());
         }
+        Ok(root)
+    }
+
+    fn get_root(&self, path: &str) -> Result<root::RepoRoot, Error> {
+        debug!("    loading repository root, path = {}", path);
+        let bytes = self.store.get_root(path)?;
+        root::RepoRoot::from_json(&bytes)
+    }
 
-        // For a `git clone` there is (in general) no git directory, so we must
-        // consult the hash passed into the program
-        if refs.len() == 0 {
-            refs.push(format!("{} refs/heads/master", handler.remote_hash()))
+    // Put the repository root onto the IPFS DAG, returning its CID. If the
+    // remote is an IPNS name, the name is updated to point to the new root.
+    // DNSLink records live outside of IPFS, so those have to be updated by
+    // hand.
+    fn publish_root(&mut self, handler: &Handler) -> Result<String, Error> {
+        // if the remote doesn't have a default branch yet, prefer the one our
+        // HEAD points to
+        let local_head = self.repo
+            .find_reference("HEAD")
+            .ok()
+            .and_then(|r| r.symbolic_target().map(|t| t.to_string()));
+
+        let root_bytes = {
+            let root = self.root(handler, true)?;
+            root.update_head(local_head.as_ref().map(|h| h.as_str()));
+            root.to_json()?
+        };
+
+        let root_cid = self.store.put_root(&root_bytes)?;
+        self.store.pin(&root_cid)?;
+
+        match *handler.target() {
+            Target::Ipns(ref name) => {
+                debug!("    publishing root {} to /ipns/{}", root_cid, name);
+                self.store
+                    .publish_name(name, &format!("/ipfs/{}", root_cid))?;
+            }
+            Target::DnsLink(ref domain) => {
+                eprintln!(
+                    "Update the DNSLink record of {} to \
+                     'dnslink=/ipfs/{}' to publish this push",
+                    domain, root_cid
+                );
+            }
+            _ => {}
         }
-        let head_ref = self.repo.find_reference("HEAD")?;
-        let head_ref_type = head_ref.kind().expect("HEAD ref type is unknown");
-        let head_ref_string = match head_ref_type {
-            git2::ReferenceType::Oid => {
-                format!("{} HEAD", head_ref.target().unwrap())
+        Ok(root_cid)
+    }
+
+    // Lists the refs of the remote, followed by its HEAD
+    fn list(
+        &mut self,
+        handler: &Handler,
+        for_push: bool,
+    ) -> Result<Vec<String>, Error> {
+        let root = self.root(handler, for_push)?;
+        let mut refs = Vec::new();
+        for (name, hash) in &root.refs {
+            refs.push(format!("{} {}", hash, name));
+            // annotated tags are followed by the object they point to
+            if let Some(peeled) = root.peeled.get(name) {
+                refs.push(format!("{} {}^{{}}", peeled, name));
             }
-            git2::ReferenceType::Symbolic => format!(
-                "@{} HEAD",
-                head_ref
-                    .symbolic_target()
-                    .expect("HEAD symbolic target is not utf-8")
-                    .to_string()
-            ),
-        };
-        refs.push(head_ref_string);
+        }
+        // the HEAD symref is what tells `git clone` which branch to check out
+        if let Some(head) = root.valid_head() {
+            refs.push(format!("@{} HEAD", head));
+        }
         Ok(refs)
     }
 
-    // `src` is the local ref being pushed, `dest` is the remote ref?
-    // Returns the hash that `src` points to
-    fn push(
-        &self,
-        src: &str,
-        dest: &str,
-        force: bool,
-    ) -> Result<Vec<u8>, Error> {
-        // get reference associated with `src`, then get src's hash
-        let src_ref = self.repo.find_reference(src)?.resolve()?;
-        let src_hash: git2::Oid = src_ref.target().unwrap();
+    // Push `src_hash`, the hash of the local ref being pushed, along with
+    // everything it links to
+    fn push(&self, src_hash: git2::Oid) -> Result<(), Error> {
         debug!("    pushing, hash = {}", src_hash);
 
-        let mut push_helper = push::PushHelper::new(&self.repo, &self.tracker);
-        push_helper.push(src_hash)?;
-        self.tracker.set_ref(&dest, &format!("{}", src_hash))?;
-        Ok(src_hash.as_bytes().to_vec())
+        let mut push_helper = push::PushHelper::new(
+            &self.repo,
+            &self.tracker,
+            &*self.store,
+            &self.options,
+        );
+        push_helper.push(src_hash)
+    }
+
+    fn delete(&mut self, dest: &str, handler: &Handler) -> Result<(), Error> {
+        debug!("    deleting {}", dest);
+        let dry_run = self.options.dry_run;
+        {
+            let root = self.root(handler, true)?;
+            if !root.refs.contains_key(dest) {
+                return Err(Error::PushRejected(
+                    "remote ref does not exist".to_string(),
+                ));
+            }
+            if dry_run {
+                return Ok(());
+            }
+            root.remove_ref(dest);
+        }
+        if self.options.verbosity > 0 {
+            eprintln!("Deleted {}", dest);
+        }
+        Ok(())
+    }
+
+    // If `oid` is an annotated tag, returns the (non-tag) object it points
+    // to, following chains of tags
+    fn peel_tag(&self, oid: git2::Oid) -> Result<Option<git2::Oid>, Error> {
+        let mut peeled = None;
+        let mut target = oid;
+        while let Ok(tag) = self.repo.find_tag(target) {
+            target = tag.target_id();
+            peeled = Some(target);
+        }
+        Ok(peeled)
+    }
+
+    // Checks that updating a ref from `current` (its published value) to
+    // `new` is a fast-forward, i.e. that `new` descends from `current`.
+    fn check_fast_forward(
+        &self,
+        current: &str,
+        new: git2::Oid,
+    ) -> Result<(), Error> {
+        let current = git2::Oid::from_str(current)?;
+        if current == new {
+            return Ok(());
+        }
+        // if we don't have the remote's commit, we can't tell whether it is
+        // an ancestor, so (like git) ask the user to fetch it first
+        if self.repo.find_commit(current).is_err() {
+            return Err(Error::PushRejected("fetch first".to_string()));
+        }
+        if !self.repo.graph_descendant_of(new, current)? {
+            return Err(Error::PushRejected("non-fast-forward".to_string()));
+        }
+        Ok(())
     }
 
     fn fetch(&self, hash: String, ref_name: String) -> Result<(), Error> {
         debug!("    fetching, hash = {}, ref_name = {}", hash, ref_name);
-        let mut fetch_helper = fetch::FetchHelper::new(&self.tracker);
+        let mut fetch_helper = fetch::FetchHelper::new(
+            &self.repo,
+            &self.tracker,
+            &*self.store,
+            &self.options,
+        );
         fetch_helper.fetch(hash.clone())?;
         self.tracker.set_ref(&ref_name, &hash)?;
         Ok(())
     }
 
     // Listen for commands coming in over stdin, respond to them by writing to
-    // stdout.
+    // stdout. Runs until stdin is closed, or until git sends a blank line
+    // outside of a batch.
     pub fn process_commands(&mut self, handler: &Handler) -> Result<(), Error> {
         let stdin = io::stdin();
-        let mut command_batch = Vec::new();
+        let mut batch = Batch::Empty;
         debug!("processing commands");
         loop {
             let mut command_line = String::new();
-            stdin.read_line(&mut command_line)?;
+            if stdin.read_line(&mut command_line)? == 0 {
+                debug!("stdin closed");
+                return Ok(());
+            }
             let command = command_line.trim_matches('\n');
 
             debug!(" -> {}", command);
@@ -138,9 +318,21 @@ impl Remote {
             if command == "capabilities" {
                 // "Lists the capabilities of the helper, one per line, ending with
                 // a blank line."
+                log_and_print("option");
                 log_and_print("push");
                 log_and_print("fetch");
                 log_and_print("");
+            } else if command.starts_with("option ") {
+                // "option <name> <value>", answered with "ok", "unsupported"
+                // or "error <msg>"
+                let params =
+                    command[(6 + 1)..].splitn(2, " ").collect::<Vec<_>>();
+                let value = if params.len() == 2 { params[1] } else { "" };
+                match self.options.set(params[0], value) {
+                    Ok(true) => log_and_print("ok"),
+                    Ok(false) => log_and_print("unsupported"),
+                    Err(msg) => log_and_print(&format!("error {}", msg)),
+                }
             } else if command.starts_with("list") {
                 // list -
                 // "Lists the refs, one per line, in the format '<value> <name>
@@ -150,79 +342,524 @@ impl Remote {
                 //
                 // list for-push -
                 // used to prepare for a `git push`
-                let refs = self.list(handler)?;
+                let refs = self.list(handler, command == "list for-push")?;
                 refs.iter().for_each(|r| log_and_print(r));
                 log_and_print("");
             } else if command.starts_with("push ") {
+                // "push +<src>:<dst>", where the '+' is optional
                 let src_dest = &command[(4 + 1)..];
+                let force = src_dest.starts_with("+");
+                let src_dest = if force { &src_dest[1..] } else { src_dest };
                 let refs = src_dest.split(":").collect::<Vec<_>>();
-                command_batch.push(Command::Push(PushArgs {
+                if refs.len() != 2 {
+                    return Err(Error::InvalidCommand(command.to_string()));
+                }
+                let args = PushArgs {
                     src: refs[0].to_string(),
                     dest: refs[1].to_string(),
-                    force: src_dest.starts_with("+"),
-                }));
+                    force: force,
+                };
+                batch = match batch {
+                    Batch::Empty => Batch::Push(vec![args]),
+                    Batch::Push(mut pushes) => {
+                        pushes.push(args);
+                        Batch::Push(pushes)
+                    }
+                    Batch::Fetch(_) => {
+                        return Err(Error::InvalidCommand(command.to_string()))
+                    }
+                };
             } else if command.starts_with("fetch ") {
+                // "fetch <sha1> <name>"
                 let params = &command[(5 + 1)..];
                 let parts = params.split(" ").collect::<Vec<_>>();
-                command_batch.push(Command::Fetch(FetchArgs {
+                if parts.len() != 2 {
+                    return Err(Error::InvalidCommand(command.to_string()));
+                }
+                let args = FetchArgs {
                     hash: parts[0].to_string(),
                     ref_name: parts[1].to_string(),
-                }));
+                };
+                batch = match batch {
+                    Batch::Empty => Batch::Fetch(vec![args]),
+                    Batch::Fetch(mut fetches) => {
+                        fetches.push(args);
+                        Batch::Fetch(fetches)
+                    }
+                    Batch::Push(_) => {
+                        return Err(Error::InvalidCommand(command.to_string()))
+                    }
+                };
             } else if command == "" {
-                for command in command_batch {
-                    self.perform_batched_command(command)?;
+                match batch {
+                    // a blank line outside of a batch means git is done
+                    Batch::Empty => return Ok(()),
+                    Batch::Push(pushes) => self.push_batch(pushes, handler),
+                    Batch::Fetch(fetches) => {
+                        self.fetch_batch(fetches, handler)?
+                    }
                 }
-                // TODO: it's weird because for push, each push
-                // should return an "ok" or "error" message, but for fetches
-                // there's just a single blank line that's output. Consequence
-                // of conflating two separate things here: blank line terminates
-                // both a push batch and a fetch batch. Should probably separate
-                // these two out
-                log_and_print("");
-                // TODO: don't return here? see above, but specifically you can
-                // have multiple push batches. returning here does not handle
-                // this correctly
-                return Ok(());
+                batch = Batch::Empty;
             } else {
                 return Err(Error::InvalidCommand(command.to_string()));
             }
         }
     }
 
-    fn perform_batched_command(
+    // Performs each push, then responds with a status line for each of them,
+    // terminated by a blank line.
+    fn push_batch(&mut self, pushes: Vec<PushArgs>, handler: &Handler) {
+        // the root as it was before this batch, to go back to if the batch
+        // ends up not being published
+        let snapshot = self.root.clone();
+        let mut updates = Vec::new();
+        let mut push_results = Vec::new();
+        for args in pushes {
+            let dest = args.dest.clone();
+            let result = self.perform_push(args, handler, &mut updates)
+                .map_err(|e| e.push_reason());
+            push_results.push((dest, result));
+        }
+
+        // with an atomic push, one failed ref fails all of them
+        let any_err = push_results.iter().any(|&(_, ref r)| r.is_err());
+        if self.options.atomic && any_err {
+            debug!("    atomic push failed, discarding ref updates");
+            fail_pushes(&mut push_results, "atomic push failed");
+            self.root = snapshot.clone();
+            updates.clear();
+        }
+
+        // the refs are only updated on the remote once the new root has been
+        // published, so if that fails, all of them failed
+        if !updates.is_empty() {
+            match self.publish_root(handler) {
+                Ok(root_cid) => {
+                    if self.options.verbosity > 0 {
+                        eprintln!("Repository root CID is {}", root_cid);
+                        if let Target::Root(_) = *handler.target() {
+                            eprintln!(
+                                "Clone with:  git clone ipgrv::{}",
+                                root_cid
+                            );
+                        }
+                    }
+                    let head = self.root.as_ref().and_then(|r| r.head.clone());
+                    updates.push(("HEAD".to_string(), head));
+                    if let Target::Root(ref cid) = *handler.target() {
+                        updates.push((published_root_key(cid), Some(root_cid)));
+                    }
+                    self.record_ref_updates(&updates);
+                }
+                Err(e) => {
+                    let reason = format!(
+                        "failed to publish repository root: {}",
+                        e.push_reason()
+                    );
+                    fail_pushes(&mut push_results, &reason);
+                    self.root = snapshot;
+                }
+            }
+        }
+        for (dest, result) in push_results {
+            match result {
+                Ok(()) => log_and_print(&format!("ok {}", dest)),
+                Err(reason) => {
+                    log_and_print(&format!("error {} {}", dest, reason))
+                }
+            }
+        }
+        log_and_print("");
+    }
+
+    // Record published ref updates in the tracker. For an atomic push they are
+    // written in a single transaction. The refs have already been published
+    // by now, so failing to record them isn't fatal.
+    fn record_ref_updates(&self, updates: &[(String, Option<String>)]) {
+        let result = if self.options.atomic {
+            self.tracker.update_refs(updates)
+        } else {
+            updates.iter().fold(Ok(()), |result, update| {
+                result.and_then(|_| match *update {
+                    (ref name, Some(ref value)) => {
+                        self.tracker.set_ref(name, value)
+                    }
+                    (ref name, None) => self.tracker.remove_ref(name),
+                })
+            })
+        };
+        if let Err(e) = result {
+            warn!("could not record ref updates in tracker: {:?}", e);
+        }
+    }
+
+    // Performs each fetch. Unlike pushes, the whole batch is answered with a
+    // single blank line once all objects are in place. Every fetch walks the
+    // objects it needs down to ones a local ref already leads to, so if git
+    // asks, the result can be reported as connected without checking again.
+    fn fetch_batch(
         &mut self,
-        command: Command,
+        fetches: Vec<FetchArgs>,
+        handler: &Handler,
     ) -> Result<(), Error> {
-        match command {
-            Command::Push(PushArgs { src, dest, force }) => {
-                let src_hash = self.push(&src, &dest, force)?;
-                eprintln!(
-                    "Pushed to IPFS as:  ipld::{}",
-                    hex::encode(&src_hash)
-                );
+        for FetchArgs { hash, ref_name } in fetches {
+            self.fetch(hash, ref_name)?;
+        }
+        if self.options.followtags {
+            self.fetch_followed_tags(handler)?;
+        }
+        if self.options.check_connectivity {
+            log_and_print("connectivity-ok");
+        }
+        log_and_print("");
+        Ok(())
+    }
+
+    // Fetch the annotated tags of the remote that point at objects we have,
+    // so that git can create them without asking for them separately
+    fn fetch_followed_tags(&mut self, handler: &Handler) -> Result<(), Error> {
+        let tags = {
+            let root = self.root(handler, false)?;
+            root.peeled
+                .iter()
+                .filter_map(|(name, peeled)| {
+                    root.refs
+                        .get(name)
+                        .map(|h| (name.clone(), h.clone(), peeled.clone()))
+                })
+                .collect::<Vec<_>>()
+        };
+        let odb = self.repo.odb()?;
+        for (name, hash, peeled) in tags {
+            if !odb.exists(git2::Oid::from_str(&peeled)?)
+                || odb.exists(git2::Oid::from_str(&hash)?)
+            {
+                continue;
+            }
+            debug!("    following tag {}", name);
+            self.fetch(hash, name)?;
+        }
+        Ok(())
+    }
+
+    // Pushes a single ref, updating the repository root. The new value of the
+    // ref (`None` if deleted) is added to `updates`, to be recorded once the
+    // root is published.
+    fn perform_push(
+        &mut self,
+        args: PushArgs,
+        handler: &Handler,
+        updates: &mut Vec<(String, Option<String>)>,
+    ) -> Result<(), Error> {
+        let PushArgs { src, dest, force } = args;
+        let force = force || self.options.force;
+
+        // an empty `src` means the remote ref should be deleted
+        if src.is_empty() {
+            self.delete(&dest, handler)?;
+            if !self.options.dry_run {
+                updates.push((dest, None));
+            }
+            return Ok(());
+        }
+
+        // get reference associated with `src`, then get src's hash
+        let src_ref = self.repo.find_reference(&src)?.resolve()?;
+        let src_hash: git2::Oid = src_ref.target().unwrap();
+
+        let current = self.root(handler, true)?.refs.get(&dest).cloned();
+        match current {
+            // like git, refuse to move an existing tag unless forced
+            Some(ref current)
+                if !force && dest.starts_with("refs/tags/")
+                    && *current != src_hash.to_string() =>
+            {
+                return Err(Error::PushRejected("already exists".to_string()))
+            }
+            Some(ref current) if !force => {
+                self.check_fast_forward(current, src_hash)?
+            }
+            Some(ref current) => {
+                debug!("    forcing update of {} from {}", dest, current)
+            }
+            None => {}
+        }
+
+        if self.options.dry_run {
+            let mut push_helper = push::PushHelper::new(
+                &self.repo,
+                &self.tracker,
+                &*self.store,
+                &self.options,
+            );
+            push_helper.push(src_hash)?;
+            if self.options.verbosity > 0 {
                 eprintln!(
-                    "Head CID is {}",
-                    ipld_git::util::sha1_to_cid(&src_hash).unwrap()
+                    "Would push {} objects ({} bytes) for {}",
+                    push_helper.object_count(),
+                    push_helper.byte_count(),
+                    dest
                 );
-                log_and_print(&format!("ok {}", src));
-            }
-            Command::Fetch(FetchArgs { hash, ref_name }) => {
-                self.fetch(hash, ref_name)?;
             }
+            return Ok(());
+        }
+
+        self.push(src_hash)?;
+        let peeled = self.peel_tag(src_hash)?.map(|oid| oid.to_string());
+        self.root(handler, true)?.set_ref(
+            &dest,
+            &src_hash.to_string(),
+            peeled,
+        );
+        updates.push((dest, Some(format!("{}", src_hash))));
+        if self.options.verbosity > 0 {
+            eprintln!("Pushed to IPFS as:  ipld::{}", src_hash);
+            eprintln!(
+                "Head CID is {}",
+                ipld_git::util::sha1_to_cid(src_hash.as_bytes()).unwrap()
+            );
         }
         Ok(())
     }
 }
 
 pub struct Handler {
-    remote_hash: String,
+    name: String,
+    url: url::RemoteUrl,
 }
 
 impl Handler {
-    pub fn new(hash: String) -> Handler {
-        Handler { remote_hash: hash }
+    // `name` is the name of the remote (or the URL itself, for a remote that
+    // isn't configured), and `url` is the URL git passes to the helper, see
+    // `url::RemoteUrl`
+    pub fn new(name: &str, url: &str) -> Result<Handler, Error> {
+        Ok(Handler {
+            name: name.to_string(),
+            url: url::RemoteUrl::parse(url)?,
+        })
     }
-    pub fn remote_hash(&self) -> &str {
-        &self.remote_hash
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn target(&self) -> &Target {
+        self.url.target()
+    }
+
+    pub fn api(&self) -> Option<&str> {
+        self.url.api()
+    }
+}
+
+// Opens the store at the address given by, in order of precedence, the `api`
+// parameter of the remote URL, the `remote.<name>.ipgrvApi` git config, or the
+// IPGRV_API environment variable. Addresses of the form `file://<dir>` are a
+// local directory (see `store::FsStore`, the URL can't name one), anything
+// else is an IPFS API endpoint. Without an address, the API of the local
+// IPFS node is used.
+fn open_store(
+    repo: &git2::Repository,
+    handler: &Handler,
+) -> Result<Box<dyn store::BlockStore>, Error> {
+    let config = repo.config()?;
+
+    let addr = if let Some(addr) = handler.api() {
+        debug!("    using API address from URL: {}", addr);
+        Some(addr.to_string())
+    } else if let Some(addr) = remote_config(&config, handler, "ipgrvApi") {
+        debug!("    using API address from git config: {}", addr);
+        Some(addr)
+    } else {
+        match env::var(API_ENV_VAR) {
+            Ok(ref addr) if addr.len() > 0 => {
+                debug!("    using API address from {}: {}", API_ENV_VAR, addr);
+                Some(addr.trim().to_string())
+            }
+            _ => None,
+        }
+    };
+
+    let mut api = match addr {
+        Some(ref addr) if addr.starts_with(FS_STORE_SCHEME) => {
+            let dir = Path::new(&addr[FS_STORE_SCHEME.len()..]);
+            return Ok(Box::new(store::FsStore::new(dir)));
+        }
+        Some(ref addr) => ipfs_api::Shell::new(addr),
+        None => ipfs_api::Shell::new_local(),
+    }.map_err(Error::ApiError)?;
+
+    if let Some(auth) = api_auth(&config, handler, &api) {
+        api.set_auth(auth);
+    }
+
+    let timeout = remote_config_number(&config, handler, "ipgrvTimeout")?
+        .unwrap_or(DEFAULT_API_TIMEOUT);
+    if timeout > 0 {
+        api.set_timeout(Duration::from_secs(timeout))
+            .map_err(Error::ApiError)?;
+    }
+    let retries = match remote_config_number(&config, handler, "ipgrvRetries")?
+    {
+        Some(retries) if retries > u64::from(MAX_API_RETRIES) => {
+            return Err(Error::InvalidConfig(format!(
+                "remote.{}.ipgrvRetries is {}, more than the maximum of {}",
+                handler.name(),
+                retries,
+                MAX_API_RETRIES
+            )))
+        }
+        // can't fail, the value is no more than the maximum
+        Some(retries) => u32::try_from(retries).unwrap_or(MAX_API_RETRIES),
+        None => DEFAULT_API_RETRIES,
+    };
+    api.set_retries(retries);
+    Ok(Box::new(api))
+}
+
+// The credentials for an API endpoint behind an authenticating proxy. A
+// bearer token is taken from the `remote.<name>.ipgrvApiToken` git config or
+// the IPGRV_API_TOKEN environment variable. Failing that, HTTPS endpoints get
+// whatever username and password git's credential helpers have stored for
+// them, as an HTTPS git remote would; `remote.<name>.ipgrvApiUser` selects
+// the username to look up.
+//
+// Credentials are only sent over HTTPS or a local Unix socket. An endpoint
+// given by the `api` parameter of the URL may come from someone else's
+// clone URL, so it only gets the token from the environment if
+// `remote.<name>.ipgrvUrlApiToken` is true.
+fn api_auth(
+    config: &git2::Config,
+    handler: &Handler,
+    api: &ipfs_api::Shell,
+) -> Option<ipfs_api::Auth> {
+    let env_token = || match env::var(API_TOKEN_ENV_VAR) {
+        Ok(token) => Some(token.trim().to_string()),
+        Err(_) => None,
+    };
+    let token = match remote_config(config, handler, "ipgrvApiToken") {
+        Some(token) => Some(token),
+        None if handler.api().is_none() => env_token(),
+        None => {
+            let name = format!("remote.{}.ipgrvUrlApiToken", handler.name());
+            match config.get_bool(&name) {
+                Ok(true) => env_token(),
+                _ => None,
+            }
+        }
+    };
+    match token {
+        Some(ref token) if token.len() > 0 => {
+            if !api.is_secure() {
+                eprintln!(
+                    "warning: not sending the API token to {} over plain \
+                     HTTP",
+                    api.url().unwrap_or("the API")
+                );
+                return None;
+            }
+            debug!("    authenticating to the API with a bearer token");
+            return Some(ipfs_api::Auth::Bearer(token.clone()));
+        }
+        _ => {}
+    }
+
+    let url = match api.url() {
+        Some(url) if url.starts_with("https://") => url,
+        _ => return None,
+    };
+    let username = remote_config(config, handler, "ipgrvApiUser");
+    let credentials = git2::CredentialHelper::new(url)
+        .config(config)
+        .username(username.as_ref().map(|u| &u[..]))
+        .execute();
+    match credentials {
+        Some((username, password)) => {
+            debug!("    authenticating to the API as {}", username);
+            Some(ipfs_api::Auth::Basic(username, password))
+        }
+        None => None,
+    }
+}
+
+// The value of `remote.<name>.<key>`, if it is set. Remotes that aren't
+// configured are named by their URL, and have no config of their own.
+fn remote_config(
+    config: &git2::Config,
+    handler: &Handler,
+    key: &str,
+) -> Option<String> {
+    let name = format!("remote.{}.{}", handler.name(), key);
+    match config.get_string(&name) {
+        Ok(value) => Some(value.trim().to_string()),
+        Err(ref e) if e.code() == git2::ErrorCode::NotFound => None,
+        Err(e) => {
+            debug!("    could not read {}: {}", name, e);
+            None
+        }
+    }
+}
+
+// Like `remote_config`, for settings that are a non-negative number
+fn remote_config_number(
+    config: &git2::Config,
+    handler: &Handler,
+    key: &str,
+) -> Result<Option<u64>, Error> {
+    match remote_config(config, handler, key) {
+        Some(value) => match value.parse::<u64>() {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => Err(Error::InvalidConfig(format!(
+                "remote.{}.{} is '{}', not a number",
+                handler.name(),
+                key,
+                value
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
+// The tracker key under which the root we last published for a remote
+// addressed by the root CID `cid` is recorded
+fn published_root_key(cid: &str) -> String {
+    format!("ROOT/{}", cid)
+}
+
+// Turn the results of the pushes that succeeded into failures
+fn fail_pushes(
+    push_results: &mut [(String, Result<(), String>)],
+    reason: &str,
+) {
+    for &mut (_, ref mut r) in push_results {
+        if r.is_ok() {
+            *r = Err(reason.to_string());
+        }
+    }
+}
+
+// Read a git object into memory, in the form it is hashed in: a header
+// ("<type> <size>\0") followed by the object's data.
+fn read_raw_object(odb: &git2::Odb, oid: git2::Oid) -> Result<Vec<u8>, Error> {
+    let odb_obj = odb.read(oid)?;
+    let raw_obj = odb_obj.data();
+
+    let mut full_obj = Vec::with_capacity(raw_obj.len() + 12);
+    match odb_obj.kind() {
+        git2::ObjectType::Blob => full_obj.extend_from_slice(b"blob "),
+        git2::ObjectType::Tree => full_obj.extend_from_slice(b"tree "),
+        git2::ObjectType::Commit => full_obj.extend_from_slice(b"commit "),
+        git2::ObjectType::Tag => full_obj.extend_from_slice(b"tag "),
+        kind => {
+            return Err(Error::Git2Error(git2::Error::from_str(&format!(
+                "object {} is a {}, not a blob, tree, commit or tag",
+                oid,
+                kind.str()
+            ))))
+        }
     }
+    full_obj.extend_from_slice(format!("{}", raw_obj.len()).as_bytes());
+    full_obj.push(0);
+    full_obj.extend_from_slice(raw_obj);
+    Ok(full_obj)
 }
@@ -0,0 +1,66 @@
+// Options set by git with the `option` command, see the "OPTIONS" section of
+// gitremote-helpers(1)
+pub struct Options {
+    // 0 is quiet, 1 is the default, higher means more verbose
+    pub verbosity: u32,
+    // Whether to print progress information to stderr
+    pub progress: bool,
+    // Go through the motions of a push without changing the remote
+    pub dry_run: bool,
+    // Fetch tags that point at fetched objects
+    pub followtags: bool,
+    // Report whether fetched objects are fully connected
+    pub check_connectivity: bool,
+    // Treat every push as a forced push
+    pub force: bool,
+    // Update either all refs of a push batch or none of them
+    pub atomic: bool,
+    // The fetch is part of a `git clone`
+    pub cloning: bool,
+}
+
+impl Options {
+    pub fn new() -> Options {
+        Options {
+            verbosity: 1,
+            progress: false,
+            dry_run: false,
+            followtags: false,
+            check_connectivity: false,
+            force: false,
+            atomic: false,
+            cloning: false,
+        }
+    }
+
+    // Sets option `name` to `value`. Returns `Ok(false)` if the option isn't
+    // supported, or an error message if `value` is invalid.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<bool, String> {
+        match name {
+            "verbosity" => {
+                self.verbosity = value
+                    .parse()
+                    .map_err(|_| format!("invalid verbosity '{}'", value))?;
+            }
+            "progress" => self.progress = parse_bool(value)?,
+            "dry-run" => self.dry_run = parse_bool(value)?,
+            "followtags" => self.followtags = parse_bool(value)?,
+            "check-connectivity" => {
+                self.check_connectivity = parse_bool(value)?
+            }
+            "force" => self.force = parse_bool(value)?,
+            "atomic" => self.atomic = parse_bool(value)?,
+            "cloning" => self.cloning = parse_bool(value)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("expected 'true' or 'false', got '{}'", value)),
+    }
+}
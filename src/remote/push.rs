@@ -1,38 +1,72 @@
 use git2;
 use ipld_git;
 use multihash;
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 
-use ipfs_api;
 use super::Error;
+use super::options::Options;
+use super::store::BlockStore;
 use super::tracker;
 
 pub struct PushHelper<'a> {
     queue: VecDeque<git2::Oid>,
     repo: &'a git2::Repository,
     tracker: &'a tracker::Tracker,
+    store: &'a dyn BlockStore,
+    options: &'a Options,
+    // Objects visited so far. In a dry run the tracker isn't updated, so this
+    // is what keeps objects from being counted twice.
+    seen: HashSet<git2::Oid>,
+    // Number of objects and bytes pushed (or that would be pushed)
+    object_count: usize,
+    byte_count: usize,
 }
 
 impl<'a> PushHelper<'a> {
     pub fn new(
         repo: &'a git2::Repository,
         tracker: &'a tracker::Tracker,
+        store: &'a dyn BlockStore,
+        options: &'a Options,
     ) -> PushHelper<'a> {
         PushHelper {
             queue: VecDeque::new(),
             repo: repo,
             tracker: tracker,
+            store: store,
+            options: options,
+            seen: HashSet::new(),
+            object_count: 0,
+            byte_count: 0,
         }
     }
 
+    pub fn object_count(&self) -> usize {
+        self.object_count
+    }
+
+    pub fn byte_count(&self) -> usize {
+        self.byte_count
+    }
+
     pub fn push(&mut self, hash: git2::Oid) -> Result<(), Error> {
         self.queue.push_back(hash);
         self.push_queue()
     }
 
-    // push each of the objects in the queue into IPFS (as IPLD).
+    // push each of the objects in the queue into IPFS (as IPLD). In a dry run,
+    // the objects are only counted.
     fn push_queue(&mut self) -> Result<(), Error> {
-        let api = ipfs_api::Shell::new_local().map_err(Error::ApiError)?;
+        let store = if self.options.dry_run {
+            None
+        } else {
+            Some(self.store)
+        };
+        let verb = if self.options.dry_run {
+            "Counting"
+        } else {
+            "Pushing"
+        };
         while let Some(oid) = self.queue.pop_front() {
             debug!("    pushing oid = {}", oid);
 
@@ -40,44 +74,43 @@ impl<'a> PushHelper<'a> {
                 debug!("    already have this oid, skipping");
                 continue;
             }
+            if !self.seen.insert(oid) {
+                continue;
+            }
 
-            let obj_bytes = self.push_object(oid, &api)?;
-
-            self.tracker.add_entry(oid.as_bytes())?;
+            let obj_bytes = self.read_object(oid)?;
+            if let Some(store) = store {
+                // the store may have the object without the tracker knowing,
+                // e.g. when it was pushed from another clone
+                if store.has_object(oid)? {
+                    debug!("    store already has this oid, not uploading");
+                } else {
+                    store.put_object(oid, &obj_bytes)?;
+                }
+                self.tracker.add_entry(oid.as_bytes())?;
+            }
 
             self.enqueue_links(&obj_bytes)?;
+
+            self.object_count += 1;
+            self.byte_count += obj_bytes.len();
+            if self.options.verbosity > 1 {
+                eprintln!("{} {}", verb, oid);
+            } else if self.options.progress {
+                eprint!("\r{} objects: {}", verb, self.object_count);
+            }
+        }
+        if self.options.progress
+            && self.options.verbosity <= 1
+            && self.object_count > 0
+        {
+            eprintln!("\r{} objects: {}, done.", verb, self.object_count);
         }
         Ok(())
     }
 
-    // Push git object into ipfs, returning the vector of bytes of the raw git
-    // object.
-    fn push_object(
-        &mut self,
-        oid: git2::Oid,
-        api: &ipfs_api::Shell,
-    ) -> Result<Vec<u8>, Error> {
-        // read the git object into memory
-        let odb = self.repo.odb()?;
-        let odb_obj = odb.read(oid)?;
-        let raw_obj = odb_obj.data();
-
-        let mut full_obj = Vec::with_capacity(raw_obj.len() + 12);
-        match odb_obj.kind() {
-            git2::ObjectType::Blob => full_obj.extend_from_slice(b"blob "),
-            git2::ObjectType::Tree => full_obj.extend_from_slice(b"tree "),
-            git2::ObjectType::Commit => full_obj.extend_from_slice(b"commit "),
-            git2::ObjectType::Tag => full_obj.extend_from_slice(b"tag "),
-            _ => unimplemented!(),
-        }
-        full_obj.extend_from_slice(format!("{}", raw_obj.len()).as_bytes());
-        full_obj.push(0);
-        full_obj.extend_from_slice(raw_obj);
-
-        // `put` the git object bytes onto the ipfs DAG.
-        api.dag_put(&full_obj, "raw", "git")
-            .map_err(Error::ApiError)?;
-        Ok(full_obj)
+    fn read_object(&self, oid: git2::Oid) -> Result<Vec<u8>, Error> {
+        super::read_raw_object(&self.repo.odb()?, oid)
     }
 
     fn enqueue_links(&mut self, obj_bytes: &[u8]) -> Result<(), Error> {
@@ -95,3 +128,51 @@ impl<'a> PushHelper<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use remote::store::FsStore;
+    use remote::testing::{self, TempDir};
+    use super::*;
+
+    #[test]
+    fn pushes_each_object_once() {
+        let dir = TempDir::new("push-once");
+        let repo = testing::init_repo(&dir, "repo");
+        let tracker = testing::tracker(&dir, "origin");
+        let store = FsStore::new(&dir.path().join("store"));
+        let options = Options::new();
+
+        let first = testing::commit(&repo, "README", "hello\n");
+        let mut helper = PushHelper::new(&repo, &tracker, &store, &options);
+        helper.push(first).unwrap();
+        // the commit, its tree and the blob
+        assert_eq!(helper.object_count(), 3);
+        assert!(store.has_object(first).unwrap());
+        assert!(tracker.has_entry(first.as_bytes()).unwrap());
+
+        // the parent and the README blob have been pushed already
+        let second = testing::commit(&repo, "NEWS", "news\n");
+        let mut helper = PushHelper::new(&repo, &tracker, &store, &options);
+        helper.push(second).unwrap();
+        assert_eq!(helper.object_count(), 3);
+        assert!(store.has_object(second).unwrap());
+    }
+
+    #[test]
+    fn dry_run_only_counts() {
+        let dir = TempDir::new("push-dry-run");
+        let repo = testing::init_repo(&dir, "repo");
+        let tracker = testing::tracker(&dir, "origin");
+        let store = FsStore::new(&dir.path().join("store"));
+        let mut options = Options::new();
+        options.dry_run = true;
+
+        let head = testing::commit(&repo, "README", "hello\n");
+        let mut helper = PushHelper::new(&repo, &tracker, &store, &options);
+        helper.push(head).unwrap();
+        assert_eq!(helper.object_count(), 3);
+        assert!(!store.has_object(head).unwrap());
+        assert!(!tracker.has_entry(head.as_bytes()).unwrap());
+    }
+}
@@ -0,0 +1,180 @@
+use chrono;
+use serde_json::{self, Map, Value};
+use std::collections::BTreeMap;
+
+use super::Error;
+
+// The repository root is a small JSON document that is put onto the IPFS DAG
+// after every push. It records every ref of the remote, along with HEAD, so
+// that anyone holding its CID can discover what the repository contains.
+// Annotated tags also have the commit they point to recorded under "peeled":
+//
+//   {
+//     "refs": { "refs/heads/master": "<hex sha1>", ... },
+//     "peeled": { "refs/tags/v1.0": "<hex sha1>", ... },
+//     "HEAD": "refs/heads/master",
+//     "meta": { "helper": "git-remote-ipgrv 0.0.1", "updated": "..." }
+//   }
+#[derive(Clone)]
+pub struct RepoRoot {
+    pub refs: BTreeMap<String, String>,
+    pub peeled: BTreeMap<String, String>,
+    pub head: Option<String>,
+}
+
+impl RepoRoot {
+    pub fn new() -> RepoRoot {
+        RepoRoot {
+            refs: BTreeMap::new(),
+            peeled: BTreeMap::new(),
+            head: None,
+        }
+    }
+
+    // Set `name` to `hash`. `peeled` is what `hash` points to if it is an
+    // annotated tag.
+    pub fn set_ref(&mut self, name: &str, hash: &str, peeled: Option<String>) {
+        self.refs.insert(name.to_string(), hash.to_string());
+        match peeled {
+            Some(peeled) => {
+                self.peeled.insert(name.to_string(), peeled);
+            }
+            None => {
+                self.peeled.remove(name);
+            }
+        }
+    }
+
+    // Remove `name`, returning whether it existed
+    pub fn remove_ref(&mut self, name: &str) -> bool {
+        self.peeled.remove(name);
+        if self.head.as_ref().map(|h| h.as_str()) == Some(name) {
+            self.head = None;
+        }
+        self.refs.remove(name).is_some()
+    }
+
+    // HEAD, if it points to a branch that exists
+    pub fn valid_head(&self) -> Option<&str> {
+        match self.head {
+            Some(ref head) if self.is_branch(head) => Some(head),
+            _ => None,
+        }
+    }
+
+    // Makes sure HEAD points to an existing branch. A valid HEAD is kept, so
+    // that pushing another branch doesn't change the remote's default branch.
+    // Otherwise `preferred` (the pusher's current branch) is used, then
+    // "master" or "main", and then any other branch.
+    pub fn update_head(&mut self, preferred: Option<&str>) {
+        if self.valid_head().is_some() {
+            return;
+        }
+        let candidates = [
+            preferred.unwrap_or(""),
+            "refs/heads/master",
+            "refs/heads/main",
+        ];
+        let new_head = candidates
+            .iter()
+            .find(|name| self.is_branch(name))
+            .map(|name| name.to_string())
+            .or_else(|| {
+                self.refs
+                    .keys()
+                    .find(|name| self.is_branch(name))
+                    .cloned()
+            });
+        self.head = new_head;
+    }
+
+    fn is_branch(&self, name: &str) -> bool {
+        name.starts_with("refs/heads/") && self.refs.contains_key(name)
+    }
+
+    pub fn from_json(bytes: &[u8]) -> Result<RepoRoot, Error> {
+        let value: Value = serde_json::from_slice(bytes)?;
+        let mut root = RepoRoot::new();
+
+        let refs = match value.get("refs").and_then(|r| r.as_object()) {
+            Some(refs) => refs,
+            None => {
+                return Err(Error::InvalidRoot(
+                    "missing 'refs' object".to_string(),
+                ))
+            }
+        };
+        for (name, target) in refs {
+            match target.as_str() {
+                Some(hash) => {
+                    root.refs.insert(name.clone(), hash.to_string());
+                }
+                None => {
+                    return Err(Error::InvalidRoot(format!(
+                        "value of ref '{}' is not a string",
+                        name
+                    )))
+                }
+            }
+        }
+
+        // "peeled" is optional, roots published before tags were supported
+        // don't have it
+        if let Some(peeled) = value.get("peeled").and_then(|p| p.as_object()) {
+            for (name, target) in peeled {
+                match target.as_str() {
+                    Some(hash) if root.refs.contains_key(name) => {
+                        root.peeled.insert(name.clone(), hash.to_string());
+                    }
+                    _ => {
+                        return Err(Error::InvalidRoot(format!(
+                            "invalid peeled value for ref '{}'",
+                            name
+                        )))
+                    }
+                }
+            }
+        }
+
+        root.head = value
+            .get("HEAD")
+            .and_then(|h| h.as_str())
+            .map(|h| h.to_string());
+        Ok(root)
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, Error> {
+        let mut refs = Map::new();
+        for (name, hash) in &self.refs {
+            refs.insert(name.clone(), Value::String(hash.clone()));
+        }
+
+        let mut peeled = Map::new();
+        for (name, hash) in &self.peeled {
+            peeled.insert(name.clone(), Value::String(hash.clone()));
+        }
+
+        let mut meta = Map::new();
+        meta.insert(
+            "helper".to_string(),
+            Value::String(format!(
+                "git-remote-ipgrv {}",
+                env!("CARGO_PKG_VERSION")
+            )),
+        );
+        meta.insert(
+            "updated".to_string(),
+            Value::String(chrono::Utc::now().to_rfc3339()),
+        );
+
+        let mut root = Map::new();
+        root.insert("refs".to_string(), Value::Object(refs));
+        root.insert("peeled".to_string(), Value::Object(peeled));
+        if let Some(ref head) = self.head {
+            root.insert("HEAD".to_string(), Value::String(head.clone()));
+        }
+        root.insert("meta".to_string(), Value::Object(meta));
+
+        Ok(serde_json::to_vec(&Value::Object(root))?)
+    }
+}
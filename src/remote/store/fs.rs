@@ -0,0 +1,177 @@
+use cid;
+use git2;
+use multihash;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use super::BlockStore;
+use remote::Error;
+
+// A store kept in a local directory, for using the helper without an IPFS
+// node, e.g. offline or in tests. It is laid out as
+//
+//   objects/<hex sha1>   the raw git objects
+//   roots/<cid>          the repository roots, as JSON
+//   names/<name>         the path each published name points to
+//
+// Roots are given a CID of their content, so that remote URLs work the same
+// as they do with IPFS.
+pub struct FsStore {
+    dir: PathBuf,
+}
+
+impl FsStore {
+    // Nothing is created until something is written, so opening a store
+    // that doesn't exist to fetch from leaves no directories behind
+    pub fn new(dir: &Path) -> FsStore {
+        FsStore {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn object_path(&self, oid: git2::Oid) -> PathBuf {
+        self.dir.join("objects").join(oid.to_string())
+    }
+}
+
+impl BlockStore for FsStore {
+    fn put_object(&self, oid: git2::Oid, object: &[u8]) -> Result<(), Error> {
+        write_file(&self.object_path(oid), object)
+    }
+
+    fn get_object(&self, oid: git2::Oid) -> Result<Vec<u8>, Error> {
+        read_file(&self.object_path(oid))
+    }
+
+    fn has_object(&self, oid: git2::Oid) -> Result<bool, Error> {
+        Ok(self.object_path(oid).exists())
+    }
+
+    fn put_root(&self, root: &[u8]) -> Result<String, Error> {
+        // a CIDv1 of the raw bytes, as the daemon would give them
+        let hash = multihash::encode(multihash::Hash::SHA2256, root)?;
+        let cid =
+            cid::Cid::new(cid::Codec::Raw, cid::Version::V1, &hash).to_string();
+        write_file(&self.dir.join("roots").join(&cid), root)?;
+        Ok(cid)
+    }
+
+    fn get_root(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let cid = path.trim_start_matches("/ipfs/");
+        read_file(&self.dir.join("roots").join(cid))
+    }
+
+    // nothing is ever removed from the directory
+    fn pin(&self, _cid: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn publish_name(&self, name: &str, path: &str) -> Result<(), Error> {
+        write_file(&self.dir.join("names").join(name), path.as_bytes())
+    }
+
+    fn resolve_name(&self, name: &str) -> Result<String, Error> {
+        let name_path = self.dir.join("names").join(name);
+        if !name_path.exists() {
+            return Err(Error::NameNotFound(name.to_string()));
+        }
+        let path = read_file(&name_path)?;
+        Ok(String::from_utf8_lossy(&path).trim().to_string())
+    }
+}
+
+// Writes go to a temporary file that is then renamed into place, so that an
+// interrupted push never leaves a partial object behind
+fn write_file(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let mut f = File::create(&tmp_path)?;
+    f.write_all(data)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, Error> {
+    let mut f = File::open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not exist", path.display()),
+        ),
+        _ => e,
+    })?;
+    let mut data = Vec::new();
+    f.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use remote::testing::TempDir;
+    use super::*;
+
+    const OID: &'static str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+    #[test]
+    fn objects() {
+        let dir = TempDir::new("fs-store-objects");
+        let store = FsStore::new(dir.path());
+        let oid = git2::Oid::from_str(OID).unwrap();
+
+        assert!(!store.has_object(oid).unwrap());
+        assert!(store.get_object(oid).is_err());
+        // reading doesn't create anything
+        assert!(!dir.path().join("objects").exists());
+        store.put_object(oid, b"blob 0\0").unwrap();
+        assert!(store.has_object(oid).unwrap());
+        assert_eq!(store.get_object(oid).unwrap(), b"blob 0\0".to_vec());
+    }
+
+    #[test]
+    fn roots() {
+        let dir = TempDir::new("fs-store-roots");
+        let store = FsStore::new(dir.path());
+
+        // a raw CIDv1 of the sha2-256 hash of the root, as IPFS would give
+        let cid = store.put_root(b"{}").unwrap();
+        let parsed = cid::Cid::from(cid.as_str()).unwrap();
+        assert_eq!(parsed.version, cid::Version::V1);
+        assert_eq!(parsed.codec, cid::Codec::Raw);
+        assert_eq!(
+            parsed.hash,
+            multihash::encode(multihash::Hash::SHA2256, b"{}").unwrap()
+        );
+        assert_eq!(store.get_root(&cid).unwrap(), b"{}".to_vec());
+        assert_eq!(
+            store.get_root(&format!("/ipfs/{}", cid)).unwrap(),
+            b"{}".to_vec()
+        );
+    }
+
+    #[test]
+    fn names() {
+        let dir = TempDir::new("fs-store-names");
+        let store = FsStore::new(dir.path());
+
+        match store.resolve_name("repo.example.com") {
+            Err(Error::NameNotFound(_)) => (),
+            result => panic!("{:?}", result),
+        }
+        store.publish_name("repo.example.com", "/ipfs/a").unwrap();
+        store.publish_name("repo.example.org", "/ipfs/b").unwrap();
+        assert_eq!(store.resolve_name("repo.example.com").unwrap(), "/ipfs/a");
+        assert_eq!(store.resolve_name("repo.example.org").unwrap(), "/ipfs/b");
+
+        // no temporary files are left behind
+        let mut names = fs::read_dir(dir.path().join("names"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["repo.example.com", "repo.example.org"]);
+    }
+}
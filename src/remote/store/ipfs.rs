@@ -0,0 +1,66 @@
+use cid;
+use git2;
+
+use ipfs_api;
+use super::{object_cid, BlockStore};
+use remote::Error;
+
+// Git objects are put onto the DAG in the `git` format, which gives them the
+// CID of their SHA-1 hash, so objects can be found by their hash alone.
+impl BlockStore for ipfs_api::Shell {
+    fn put_object(&self, oid: git2::Oid, object: &[u8]) -> Result<(), Error> {
+        let cid = self.dag_put(object, "raw", "git", true)
+            .map_err(Error::ApiError)?;
+
+        // make sure the daemon stored the object where it will be looked for
+        let expected_cid = object_cid(oid)?;
+        if cid::Cid::from(cid.as_str())?
+            != cid::Cid::from(expected_cid.as_str())?
+        {
+            return Err(Error::CidMismatch(expected_cid, cid));
+        }
+        Ok(())
+    }
+
+    fn get_object(&self, oid: git2::Oid) -> Result<Vec<u8>, Error> {
+        let cid = object_cid(oid)?;
+        debug!("    the corresponding cid = {}", cid);
+        self.block_get(&cid).map_err(|e| match e {
+            ipfs_api::Error::Timeout(secs) => {
+                Error::ObjectNotFound(oid.to_string(), secs)
+            }
+            e => Error::ApiError(e),
+        })
+    }
+
+    fn has_object(&self, oid: git2::Oid) -> Result<bool, Error> {
+        self.has_block(&object_cid(oid)?).map_err(Error::ApiError)
+    }
+
+    fn put_root(&self, root: &[u8]) -> Result<String, Error> {
+        self.dag_put_json(root).map_err(Error::ApiError)
+    }
+
+    fn get_root(&self, path: &str) -> Result<Vec<u8>, Error> {
+        self.dag_get(path).map_err(Error::ApiError)
+    }
+
+    fn pin(&self, cid: &str) -> Result<(), Error> {
+        self.pin_add(cid).map_err(Error::ApiError)
+    }
+
+    fn publish_name(&self, name: &str, path: &str) -> Result<(), Error> {
+        self.name_publish(path, name).map_err(Error::ApiError)
+    }
+
+    fn resolve_name(&self, name: &str) -> Result<String, Error> {
+        match self.name_resolve(&format!("/ipns/{}", name)) {
+            Ok(path) => Ok(path),
+            Err(ipfs_api::Error::NotFound(ref e)) => {
+                debug!("    could not resolve {}: {}", name, e);
+                Err(Error::NameNotFound(name.to_string()))
+            }
+            Err(e) => Err(Error::ApiError(e)),
+        }
+    }
+}
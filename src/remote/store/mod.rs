@@ -0,0 +1,48 @@
+use git2;
+use ipld_git;
+
+use super::Error;
+
+pub use self::fs::FsStore;
+
+mod fs;
+mod ipfs;
+
+// Where the objects and repository roots of a remote are kept. The IPFS HTTP
+// API (`ipfs_api::Shell`) is the store in normal use, `FsStore` keeps
+// everything in a local directory instead.
+pub trait BlockStore {
+    // Store the git object `oid` in its raw form: a header ("<type> <size>\0")
+    // followed by the object's data
+    fn put_object(&self, oid: git2::Oid, object: &[u8]) -> Result<(), Error>;
+
+    // The raw form of the git object `oid`. Stores don't verify what they
+    // return, that is up to the caller.
+    fn get_object(&self, oid: git2::Oid) -> Result<Vec<u8>, Error>;
+
+    // Whether the store already has the git object `oid`
+    fn has_object(&self, oid: git2::Oid) -> Result<bool, Error>;
+
+    // Store a repository root (a JSON document), returning its CID
+    fn put_root(&self, root: &[u8]) -> Result<String, Error>;
+
+    // The repository root at `path`, which is a CID or an `/ipfs/` path
+    fn get_root(&self, path: &str) -> Result<Vec<u8>, Error>;
+
+    // Make sure the store keeps the repository root `cid` around
+    fn pin(&self, cid: &str) -> Result<(), Error>;
+
+    // Point the name `name` (an IPNS name) at `path`
+    fn publish_name(&self, name: &str, path: &str) -> Result<(), Error>;
+
+    // The path `name` points to. A name that has never been published gives
+    // `Error::NameNotFound`.
+    fn resolve_name(&self, name: &str) -> Result<String, Error>;
+}
+
+// The CID of the git object `oid` on IPFS
+pub fn object_cid(oid: git2::Oid) -> Result<String, Error> {
+    let cid = ipld_git::util::sha1_to_cid(oid.as_bytes())
+        .map_err(Error::IpldGitError)?;
+    Ok(cid.to_string())
+}
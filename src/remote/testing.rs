@@ -0,0 +1,72 @@
+use git2;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use super::tracker::Tracker;
+
+// Helpers for the unit tests of the remote helper
+
+// A directory under the system's temporary directory, removed when dropped.
+// `name` has to be unique among the tests.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!(
+            "ipgrv-test-{}-{}",
+            process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn init_repo(dir: &TempDir, name: &str) -> git2::Repository {
+    git2::Repository::init(dir.path().join(name)).unwrap()
+}
+
+// A tracker kept in its own directory under `dir`, with `name` as namespace
+pub fn tracker(dir: &TempDir, name: &str) -> Tracker {
+    let path = dir.path().join(format!("{}-tracker", name));
+    fs::create_dir_all(&path).unwrap();
+    Tracker::new(path.to_str().unwrap(), name).unwrap()
+}
+
+// Commits the file `name` with `contents` on top of HEAD, returning the new
+// commit
+pub fn commit(
+    repo: &git2::Repository,
+    name: &str,
+    contents: &str,
+) -> git2::Oid {
+    let parent = repo
+        .head()
+        .ok()
+        .and_then(|head| head.target())
+        .map(|oid| repo.find_commit(oid).unwrap());
+    let parent_tree = parent.as_ref().map(|p| p.tree().unwrap());
+
+    let blob = repo.blob(contents.as_bytes()).unwrap();
+    let mut builder = repo.treebuilder(parent_tree.as_ref()).unwrap();
+    builder.insert(name, blob, 0o100644).unwrap();
+    let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+
+    let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+    let parents = parent.iter().collect::<Vec<_>>();
+    repo.commit(Some("HEAD"), &signature, &signature, name, &tree, &parents)
+        .unwrap()
+}
@@ -1,11 +1,17 @@
 use lmdb;
 
+// The tracker records which objects have been pushed or fetched, and the
+// value each ref of a remote had the last time we talked to it. Both are
+// kept per remote, as remotes may use different stores: objects are keyed
+// by "<namespace>/objects/" followed by their 20-byte SHA-1 digest, and refs
+// by "<namespace>/<ref name>", the namespace being the name of the remote.
 pub struct Tracker {
     db: lmdb::Database<'static>,
+    namespace: String,
 }
 
 impl Tracker {
-    pub fn new(path: &str) -> Result<Tracker, lmdb::Error> {
+    pub fn new(path: &str, namespace: &str) -> Result<Tracker, lmdb::Error> {
         let env = unsafe {
             lmdb::EnvBuilder::new()?.open(
                 path,
@@ -18,7 +24,20 @@ impl Tracker {
         let db =
             lmdb::Database::open(env, None, &lmdb::DatabaseOptions::defaults())
                 .unwrap();
-        Ok(Tracker { db: db })
+        Ok(Tracker {
+            db: db,
+            namespace: namespace.to_string(),
+        })
+    }
+
+    fn ref_key(&self, ref_name: &str) -> String {
+        format!("{}/{}", self.namespace, ref_name)
+    }
+
+    fn object_key(&self, hash: &[u8]) -> Vec<u8> {
+        let mut key = format!("{}/objects/", self.namespace).into_bytes();
+        key.extend_from_slice(hash);
+        key
     }
 
     pub fn add_entry(&self, hash: &[u8]) -> Result<(), lmdb::Error> {
@@ -27,7 +46,12 @@ impl Tracker {
 
         {
             let mut access = txn.access();
-            access.put(&self.db, hash, &(), lmdb::put::Flags::empty())?;
+            access.put(
+                &self.db,
+                self.object_key(hash).as_slice(),
+                &(),
+                lmdb::put::Flags::empty(),
+            )?;
         }
 
         txn.commit()
@@ -38,7 +62,8 @@ impl Tracker {
         let env = self.db.env();
         let txn = lmdb::ReadTransaction::new(env)?;
         let access = txn.access();
-        match access.get::<_, ()>(&self.db, hash) {
+        let key = self.object_key(hash);
+        match access.get::<_, ()>(&self.db, key.as_slice()) {
             Ok(_) => Ok(true),
             Err(e) => match e {
                 lmdb::Error::Code(lmdb::error::NOTFOUND) => Ok(false),
@@ -59,7 +84,7 @@ impl Tracker {
             let mut access = txn.access();
             access.put(
                 &self.db,
-                ref_name,
+                self.ref_key(ref_name).as_str(),
                 ref_value,
                 lmdb::put::Flags::empty(),
             )?;
@@ -68,6 +93,57 @@ impl Tracker {
         txn.commit()
     }
 
+    pub fn remove_ref(&self, ref_name: &str) -> Result<(), lmdb::Error> {
+        let env = self.db.env();
+        let txn = lmdb::WriteTransaction::new(env)?;
+
+        {
+            let mut access = txn.access();
+            match access.del_key(&self.db, self.ref_key(ref_name).as_str()) {
+                Ok(()) | Err(lmdb::Error::Code(lmdb::error::NOTFOUND)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        txn.commit()
+    }
+
+    // Set (`Some`) or remove (`None`) several refs in a single transaction, so
+    // that either all of them are updated or none are
+    pub fn update_refs(
+        &self,
+        updates: &[(String, Option<String>)],
+    ) -> Result<(), lmdb::Error> {
+        let env = self.db.env();
+        let txn = lmdb::WriteTransaction::new(env)?;
+
+        {
+            let mut access = txn.access();
+            for &(ref ref_name, ref ref_value) in updates {
+                match *ref_value {
+                    Some(ref value) => access.put(
+                        &self.db,
+                        self.ref_key(ref_name).as_str(),
+                        value.as_str(),
+                        lmdb::put::Flags::empty(),
+                    )?,
+                    None => {
+                        let key = self.ref_key(ref_name);
+                        match access.del_key(&self.db, key.as_str()) {
+                            Ok(())
+                            | Err(lmdb::Error::Code(lmdb::error::NOTFOUND)) => {
+                                ()
+                            }
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+            }
+        }
+
+        txn.commit()
+    }
+
     pub fn get_ref(
         &self,
         ref_name: &str,
@@ -75,7 +151,7 @@ impl Tracker {
         let env = self.db.env();
         let txn = lmdb::ReadTransaction::new(env)?;
         let access = txn.access();
-        match access.get::<_, str>(&self.db, ref_name) {
+        match access.get::<_, str>(&self.db, self.ref_key(ref_name).as_str()) {
             // TODO: no copy here? that would mean, I think, that this API is
             // busted, and you need a function that returns a read transaction
             // to the code that is looking to reference a value from the db.
@@ -86,4 +162,38 @@ impl Tracker {
             },
         }
     }
+
+    // All refs (under "refs/") recorded for this namespace, as pairs of ref
+    // name and value
+    pub fn refs(&self) -> Result<Vec<(String, String)>, lmdb::Error> {
+        let prefix = self.ref_key("refs/");
+        let env = self.db.env();
+        let txn = lmdb::ReadTransaction::new(env)?;
+        let access = txn.access();
+        let mut cursor = txn.cursor(&self.db)?;
+
+        let mut refs = Vec::new();
+        // keys are sorted, so all the refs are found in one run starting
+        // from the prefix
+        let mut entry =
+            cursor.seek_range_k::<[u8], [u8]>(&access, prefix.as_bytes());
+        loop {
+            match entry {
+                Ok((key, value)) => {
+                    if !key.starts_with(prefix.as_bytes()) {
+                        break;
+                    }
+                    let ref_name = &key[(self.namespace.len() + 1)..];
+                    refs.push((
+                        String::from_utf8_lossy(ref_name).into_owned(),
+                        String::from_utf8_lossy(value).into_owned(),
+                    ));
+                }
+                Err(lmdb::Error::Code(lmdb::error::NOTFOUND)) => break,
+                Err(e) => return Err(e),
+            }
+            entry = cursor.next::<[u8], [u8]>(&access);
+        }
+        Ok(refs)
+    }
 }
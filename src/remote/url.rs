@@ -0,0 +1,291 @@
+use bs58;
+use cid;
+use url::form_urlencoded;
+
+use super::{Error, FS_STORE_SCHEME};
+
+const SCHEME: &'static str = "ipgrv://";
+
+// The identity multihash function, and the length of the ed25519 public keys
+// (in their protobuf encoding) that peer IDs inline with it
+const IDENTITY_HASH: u8 = 0x00;
+const ED25519_KEY_LEN: usize = 36;
+
+// What a remote URL points at
+#[derive(Debug, PartialEq)]
+pub enum Target {
+    // The hash of a commit, taken to be `refs/heads/master` (legacy)
+    GitHash(String),
+    // The CID of a published repository root
+    Root(String),
+    // An IPNS name that resolves to a repository root
+    Ipns(String),
+    // A domain with a DNSLink TXT record pointing to a repository root
+    DnsLink(String),
+}
+
+// A parsed remote URL. git hands the helper everything after `ipgrv::` for
+// URLs of the form `ipgrv::<address>`, and the whole URL for URLs of the form
+// `ipgrv://<address>`. The address can be any of
+//
+//   <40 hex digits>            a git commit hash
+//   <cid>                      a repository root, CIDv0 or multibase CIDv1
+//   /ipfs/<cid>                same as above
+//   /ipns/<name>               an IPNS name (peer ID or DNSLink domain)
+//   <domain>                   a DNSLink domain, e.g. `repo.example.com`
+//
+// optionally followed by query parameters:
+//
+//   ?api=<multiaddr or URL>    the IPFS API endpoint to use
+//
+// A directory store (`file://<dir>`) can't be given in the URL, since URLs
+// are passed around and a clone shouldn't read or write local paths picked
+// by whoever wrote the URL.
+pub struct RemoteUrl {
+    target: Target,
+    api: Option<String>,
+}
+
+impl RemoteUrl {
+    pub fn parse(url: &str) -> Result<RemoteUrl, Error> {
+        let invalid = |reason: &str| {
+            Error::InvalidUrl(format!("'{}': {}", url, reason))
+        };
+
+        let rest = if url.starts_with(SCHEME) {
+            &url[SCHEME.len()..]
+        } else {
+            url
+        };
+        let (address, query) = match rest.find('?') {
+            Some(i) => (&rest[..i], Some(&rest[(i + 1)..])),
+            None => (rest, None),
+        };
+
+        let mut api = None;
+        if let Some(query) = query {
+            for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                match &*key {
+                    "api" if value.starts_with(FS_STORE_SCHEME) => {
+                        return Err(invalid(
+                            "a directory store can only be set with \
+                             remote.<name>.ipgrvApi or IPGRV_API",
+                        ))
+                    }
+                    "api" if !value.is_empty() => {
+                        api = Some(value.into_owned())
+                    }
+                    "api" => return Err(invalid("empty 'api' parameter")),
+                    _ => {
+                        return Err(invalid(&format!(
+                            "unknown parameter '{}'",
+                            key
+                        )))
+                    }
+                }
+            }
+        }
+
+        let address = address.trim_matches('/');
+        if address.is_empty() {
+            return Err(invalid("no repository address given"));
+        }
+        let parts = address.split('/').collect::<Vec<_>>();
+
+        let target = match (parts[0], parts.len()) {
+            ("ipfs", 2) if is_cid(parts[1]) => {
+                Target::Root(parts[1].to_string())
+            }
+            ("ipfs", 2) => {
+                return Err(invalid(&format!("'{}' is not a CID", parts[1])))
+            }
+            ("ipns", 2) if is_domain(parts[1]) => {
+                Target::DnsLink(parts[1].to_string())
+            }
+            ("ipns", 2) if is_peer_id(parts[1]) => {
+                Target::Ipns(parts[1].to_string())
+            }
+            ("ipns", 2) => {
+                return Err(invalid(&format!(
+                    "'{}' is neither a peer ID nor a domain",
+                    parts[1]
+                )))
+            }
+            ("ipfs", _) | ("ipns", _) => {
+                return Err(invalid(&format!(
+                    "expected '/{}/<name>'",
+                    parts[0]
+                )))
+            }
+            (single, 1) if is_sha1_hex(single) => {
+                Target::GitHash(single.to_string())
+            }
+            (single, 1) if is_cid(single) => Target::Root(single.to_string()),
+            (single, 1) if is_domain(single) => {
+                Target::DnsLink(single.to_string())
+            }
+            _ => {
+                return Err(invalid(
+                    "expected a git hash, a CID, '/ipfs/<cid>', \
+                     '/ipns/<name>' or a DNSLink domain",
+                ))
+            }
+        };
+
+        Ok(RemoteUrl {
+            target: target,
+            api: api,
+        })
+    }
+
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
+
+    // The IPFS API endpoint given with the `api` parameter, if any
+    pub fn api(&self) -> Option<&str> {
+        self.api.as_ref().map(|s| s.as_str())
+    }
+}
+
+fn is_sha1_hex(s: &str) -> bool {
+    s.len() == 40 && s.chars().all(|c| c.is_digit(16))
+}
+
+// Checks that `s` is a CID: either a CIDv0 (a base58 sha2-256 multihash,
+// always starting with "Qm"), or a CIDv1 in a multibase encoding
+fn is_cid(s: &str) -> bool {
+    cid::Cid::from(s).is_ok()
+}
+
+// Peer IDs are either CIDs of the public key, or the bare base58 encoding of
+// an identity multihash of an ed25519 key (which always starts with "12D3")
+fn is_peer_id(s: &str) -> bool {
+    if is_cid(s) {
+        return true;
+    }
+    match bs58::decode(s).into_vec() {
+        Ok(bytes) => {
+            bytes.len() == ED25519_KEY_LEN + 2
+                && bytes[0] == IDENTITY_HASH
+                && bytes[1] as usize == ED25519_KEY_LEN
+        }
+        Err(_) => false,
+    }
+}
+
+fn is_domain(s: &str) -> bool {
+    let labels = s.split('.').collect::<Vec<_>>();
+    labels.len() >= 2 && labels.iter().all(|label| {
+        !label.is_empty() && label.len() <= 63 && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &'static str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+    const CID_V0: &'static str =
+        "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG";
+    const CID_BASE32: &'static str =
+        "bafybeie5nqv6kd3qnfjupgvz34woh3oksc3iau6abmyajn7qvtf6d2ho34";
+    const CID_BASE58: &'static str =
+        "zdj7Wg2Qkk4mYgAkVU1kppfQ2sMGz5zPwERVpeWmxCQLDxVoC";
+    const PEER_ID: &'static str =
+        "12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN";
+
+    fn target(url: &str) -> Target {
+        match RemoteUrl::parse(url) {
+            Ok(parsed) => parsed.target,
+            Err(e) => panic!("could not parse '{}': {}", url, e),
+        }
+    }
+
+    #[test]
+    fn git_hash() {
+        assert_eq!(target(HASH), Target::GitHash(HASH.to_string()));
+    }
+
+    #[test]
+    fn root_cids() {
+        for cid in &[CID_V0, CID_BASE32, CID_BASE58] {
+            assert_eq!(target(cid), Target::Root(cid.to_string()));
+            assert_eq!(
+                target(&format!("/ipfs/{}", cid)),
+                Target::Root(cid.to_string())
+            );
+        }
+        assert_eq!(
+            target(&format!("ipgrv://{}", CID_BASE32)),
+            Target::Root(CID_BASE32.to_string())
+        );
+    }
+
+    #[test]
+    fn ipns_names() {
+        assert_eq!(
+            target(&format!("/ipns/{}", PEER_ID)),
+            Target::Ipns(PEER_ID.to_string())
+        );
+        assert_eq!(
+            target(&format!("/ipns/{}", CID_BASE32)),
+            Target::Ipns(CID_BASE32.to_string())
+        );
+        assert_eq!(
+            target("/ipns/repo.example.com"),
+            Target::DnsLink("repo.example.com".to_string())
+        );
+        assert_eq!(
+            target("repo.example.com"),
+            Target::DnsLink("repo.example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn api_parameter() {
+        let url = format!("/ipns/{}?api=/ip4/127.0.0.1/tcp/5001", PEER_ID);
+        let parsed = RemoteUrl::parse(&url).unwrap();
+        assert_eq!(parsed.api(), Some("/ip4/127.0.0.1/tcp/5001"));
+
+        let url = format!("{}?api=https%3A%2F%2Fipfs.example.com", CID_V0);
+        let parsed = RemoteUrl::parse(&url).unwrap();
+        assert_eq!(parsed.api(), Some("https://ipfs.example.com"));
+
+        assert_eq!(RemoteUrl::parse(HASH).unwrap().api(), None);
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        assert!(RemoteUrl::parse(&format!("{}?api=", HASH)).is_err());
+        assert!(RemoteUrl::parse(&format!("{}?foo=bar", HASH)).is_err());
+        assert!(
+            RemoteUrl::parse(&format!("{}?api=file:///tmp/store", HASH))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn malformed_addresses() {
+        for url in &[
+            "",
+            "/",
+            "bogusnamexx",
+            "/ipfs/bogusnamexx",
+            "/ipfs/repo.example.com",
+            "/ipns/bogusnamexx",
+            "/ipns/12D3",
+            &format!("/ipns/{}", &PEER_ID[..40]),
+            "/ipns/",
+            &format!("/ipfs/{}/extra", CID_V0),
+            // a truncated CID
+            &CID_BASE32[..40],
+            &HASH[1..],
+            "not a domain.com",
+        ] {
+            assert!(RemoteUrl::parse(url).is_err(), "{}", url);
+        }
+    }
+}
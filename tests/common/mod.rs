@@ -0,0 +1,475 @@
+// Support for the end-to-end tests: a stand-in for the IPFS HTTP API that
+// keeps everything in memory, and a harness running git (and through it the
+// helper) in temporary repositories.
+#![allow(dead_code)]
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use base32;
+use bs58;
+use sha1;
+
+const BASE32: base32::Alphabet = base32::Alphabet::RFC4648 { padding: false };
+
+// CID prefixes (version, codec, multihash function and length) of git
+// objects and of dag-cbor nodes. The mock gives every node a SHA-1 multihash.
+const GIT_CID_PREFIX: &'static [u8] = &[0x01, 0x78, 0x11, 0x14];
+const CBOR_CID_PREFIX: &'static [u8] = &[0x01, 0x71, 0x11, 0x14];
+
+#[derive(Default)]
+struct State {
+    // the content of each block, by the binary form of its CID
+    blocks: HashMap<Vec<u8>, Vec<u8>>,
+    // IPNS names and the paths they point to
+    names: HashMap<String, String>,
+    pins: HashSet<Vec<u8>>,
+    // how many blocks have been fetched with `block/get`
+    block_gets: usize,
+}
+
+// An IPFS API server implementing just the commands the helper uses:
+// `dag/put`, `dag/get`, `block/get`, `block/stat`, `name/publish`,
+// `name/resolve` and `pin/add`. It runs until the test process exits.
+pub struct MockIpfs {
+    port: u16,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockIpfs {
+    pub fn start() -> MockIpfs {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let server_state = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let state = server_state.clone();
+                thread::spawn(move || {
+                    if let Err(e) = serve(stream, &state) {
+                        eprintln!("mock IPFS: {}", e);
+                    }
+                });
+            }
+        });
+
+        MockIpfs {
+            port: port,
+            state: state,
+        }
+    }
+
+    pub fn multiaddr(&self) -> String {
+        format!("/ip4/127.0.0.1/tcp/{}", self.port)
+    }
+
+    // The path an IPNS name was last published with
+    pub fn resolve(&self, name: &str) -> Option<String> {
+        self.state.lock().unwrap().names.get(name).cloned()
+    }
+
+    pub fn is_pinned(&self, path: &str) -> bool {
+        let cid = path.trim_start_matches("/ipfs/");
+        match decode_cid(cid) {
+            Some(cid) => self.state.lock().unwrap().pins.contains(&cid),
+            None => false,
+        }
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.state.lock().unwrap().blocks.len()
+    }
+
+    pub fn block_gets(&self) -> usize {
+        self.state.lock().unwrap().block_gets
+    }
+}
+
+struct Request {
+    command: String,
+    params: Vec<(String, String)>,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|&&(ref n, _)| n == name)
+            .map(|&(_, ref v)| v.as_str())
+    }
+}
+
+fn serve(stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = read_request(&mut reader)?;
+    let (status, body) = handle(&request, &mut state.lock().unwrap());
+
+    let reason = if status == 200 { "OK" } else { "Error" };
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason,
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    stream.flush()
+}
+
+fn handle(request: &Request, state: &mut State) -> (u16, Vec<u8>) {
+    let arg = request.param("arg").unwrap_or("");
+    match request.command.as_str() {
+        "dag/put" => {
+            let data =
+                match multipart_file(&request.content_type, &request.body) {
+                    Some(data) => data,
+                    None => {
+                        return error(
+                            400,
+                            "file argument 'object data' is required",
+                        )
+                    }
+                };
+            let prefix = if request.param("format") == Some("git") {
+                GIT_CID_PREFIX
+            } else {
+                CBOR_CID_PREFIX
+            };
+            let mut cid = prefix.to_vec();
+            cid.extend_from_slice(&sha1_digest(&data));
+            if request.param("pin") == Some("true") {
+                state.pins.insert(cid.clone());
+            }
+            state.blocks.insert(cid.clone(), data);
+            json(&format!("{{\"Cid\":{{\"/\":\"{}\"}}}}", encode_cid(&cid)))
+        }
+        "dag/get" | "block/get" => {
+            if request.command == "block/get" {
+                state.block_gets += 1;
+            }
+            let cid = decode_cid(arg.trim_start_matches("/ipfs/"));
+            match cid.and_then(|cid| state.blocks.get(&cid)) {
+                Some(data) => (200, data.clone()),
+                None => error(500, "blockservice: key not found"),
+            }
+        }
+        "block/stat" => {
+            let cid = decode_cid(arg);
+            match cid.and_then(|cid| state.blocks.get(&cid)) {
+                Some(data) => json(&format!(
+                    "{{\"Key\":\"{}\",\"Size\":{}}}",
+                    arg,
+                    data.len()
+                )),
+                None => error(500, "blockservice: key not found"),
+            }
+        }
+        "name/publish" => {
+            let key = request.param("key").unwrap_or("self").to_string();
+            state.names.insert(key.clone(), arg.to_string());
+            json(&format!("{{\"Name\":\"{}\",\"Value\":\"{}\"}}", key, arg))
+        }
+        "name/resolve" => {
+            let name = arg.trim_start_matches("/ipns/");
+            match state.names.get(name) {
+                Some(path) => json(&format!("{{\"Path\":\"{}\"}}", path)),
+                None => error(500, "could not resolve name"),
+            }
+        }
+        "pin/add" => match decode_cid(arg.trim_start_matches("/ipfs/")) {
+            Some(cid) => {
+                state.pins.insert(cid);
+                json(&format!("{{\"Pins\":[\"{}\"]}}", arg))
+            }
+            None => error(500, "invalid path"),
+        },
+        _ => (404, b"404 page not found".to_vec()),
+    }
+}
+
+fn json(body: &str) -> (u16, Vec<u8>) {
+    (200, body.as_bytes().to_vec())
+}
+
+// An error response, in the form the daemon sends them
+fn error(status: u16, message: &str) -> (u16, Vec<u8>) {
+    let body = format!(
+        "{{\"Message\":\"{}\",\"Code\":0,\"Type\":\"error\"}}",
+        message
+    );
+    (status, body.into_bytes())
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    // e.g. "POST /api/v0/dag/put?format=git HTTP/1.1"
+    let target = line.split_whitespace().nth(1).unwrap_or("").to_string();
+    let (path, query) = match target.find('?') {
+        Some(i) => (&target[..i], &target[(i + 1)..]),
+        None => (&target[..], ""),
+    };
+
+    let mut content_type = String::new();
+    let mut chunked = false;
+    let mut length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let mut split = header.splitn(2, ':');
+        let name = split.next().unwrap_or("").trim().to_lowercase();
+        let value = split.next().unwrap_or("").trim();
+        match name.as_str() {
+            "content-type" => content_type = value.to_string(),
+            "content-length" => length = value.parse().unwrap_or(0),
+            "transfer-encoding" => chunked = value.contains("chunked"),
+            _ => {}
+        }
+    }
+
+    let mut body = Vec::new();
+    if chunked {
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            let size = line.split(';').next().unwrap_or("").trim();
+            let size = usize::from_str_radix(size, 16).unwrap_or(0);
+            if size == 0 {
+                // the (empty) trailer
+                line.clear();
+                reader.read_line(&mut line)?;
+                break;
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..])?;
+            line.clear();
+            reader.read_line(&mut line)?;
+        }
+    } else {
+        body.resize(length, 0);
+        reader.read_exact(&mut body)?;
+    }
+
+    Ok(Request {
+        command: path.trim_start_matches("/api/v0/").to_string(),
+        params: parse_query(query),
+        content_type: content_type,
+        body: body,
+    })
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut split = pair.splitn(2, '=');
+            let name = percent_decode(split.next().unwrap_or(""));
+            let value = percent_decode(split.next().unwrap_or(""));
+            (name, value)
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = String::from_utf8_lossy(&bytes[(i + 1)..(i + 3)]);
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(b) => {
+                        decoded.push(b);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// The content of the first part of a multipart/form-data body
+fn multipart_file(content_type: &str, body: &[u8]) -> Option<Vec<u8>> {
+    let boundary = content_type.split("boundary=").nth(1)?.trim_matches('"');
+    let delimiter = format!("\r\n--{}", boundary);
+    // the part's content starts after its headers
+    let start = find(body, b"\r\n\r\n")? + 4;
+    let end = start + find(&body[start..], delimiter.as_bytes())?;
+    Some(body[start..end].to_vec())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// CIDv1s are handed out in base32, as the daemon does by default
+fn encode_cid(cid: &[u8]) -> String {
+    let encoded = base32::encode(BASE32, cid);
+    format!("b{}", encoded.to_lowercase())
+}
+
+// Decodes CIDv1s in base32 (as the mock hands them out) or base58btc (as
+// the helper formats the CIDs of git objects)
+fn decode_cid(cid: &str) -> Option<Vec<u8>> {
+    if cid.starts_with('b') {
+        base32::decode(BASE32, &cid[1..].to_uppercase())
+    } else if cid.starts_with('z') {
+        bs58::decode(&cid[1..]).into_vec().ok()
+    } else {
+        None
+    }
+}
+
+// The mock has to name git objects by their SHA-1 hash, like the daemon does
+fn sha1_digest(data: &[u8]) -> [u8; 20] {
+    sha1::Sha1::from(data).digest().bytes()
+}
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+// A temporary directory to run git in, with the helper on the PATH and the
+// IPFS API address it should use in the environment. The directory doubles
+// as HOME, so the user's git config doesn't get in the way.
+pub struct Harness {
+    dir: PathBuf,
+    api: String,
+}
+
+impl Harness {
+    pub fn new(api: &str) -> Harness {
+        let dir = env::temp_dir().join(format!(
+            "ipgrv-test-{}-{}",
+            process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        Harness {
+            dir: dir,
+            api: api.to_string(),
+        }
+    }
+
+    // Use the IPFS API (or store) at `api` from now on
+    pub fn set_api(&mut self, api: &str) {
+        self.api = api.to_string();
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    // Creates a repository with `master` checked out, and no commits
+    pub fn init_repo(&self, name: &str) -> PathBuf {
+        let repo = self.path(name);
+        fs::create_dir_all(&repo).unwrap();
+        self.git(&repo, &["init", "-q"]);
+        self.git(&repo, &["symbolic-ref", "HEAD", "refs/heads/master"]);
+        repo
+    }
+
+    // Writes `contents` to `file` and commits it, returning the new commit
+    pub fn commit(
+        &self,
+        repo: &Path,
+        file: &str,
+        contents: &str,
+        message: &str,
+    ) -> String {
+        fs::write(repo.join(file), contents).unwrap();
+        self.git(repo, &["add", file]);
+        self.git(repo, &["commit", "-q", "-m", message]);
+        self.rev_parse(repo, "HEAD")
+    }
+
+    pub fn rev_parse(&self, repo: &Path, rev: &str) -> String {
+        self.git(repo, &["rev-parse", rev]).trim().to_string()
+    }
+
+    // Runs git, panicking if it fails, and returns what it printed
+    pub fn git(&self, cwd: &Path, args: &[&str]) -> String {
+        let output = self.run_git(cwd, args);
+        if !output.status.success() {
+            panic!(
+                "git {} failed:\n{}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    // Runs git, panicking if it succeeds, and returns its error output
+    pub fn git_fails(&self, cwd: &Path, args: &[&str]) -> String {
+        let output = self.run_git(cwd, args);
+        if output.status.success() {
+            panic!("git {} unexpectedly succeeded", args.join(" "));
+        }
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    }
+
+    fn run_git(&self, cwd: &Path, args: &[&str]) -> Output {
+        let helper = Path::new(env!("CARGO_BIN_EXE_git-remote-ipgrv"));
+        let mut paths = vec![helper.parent().unwrap().to_path_buf()];
+        if let Some(path) = env::var_os("PATH") {
+            paths.extend(env::split_paths(&path));
+        }
+
+        Command::new("git")
+            .args(args)
+            .current_dir(cwd)
+            .env("PATH", env::join_paths(paths).unwrap())
+            .env("HOME", &self.dir)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .env("IPGRV_API", &self.api)
+            .env_remove("GIT_DIR")
+            .env_remove("IPFS_PATH")
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,235 @@
+// End-to-end tests running `git push`, `git clone` and `git fetch` against
+// the helper, with the mock IPFS API (or a directory store) standing in for
+// an IPFS node.
+
+extern crate base32;
+extern crate bs58;
+extern crate sha1;
+
+mod common;
+
+use std::fs;
+
+use common::{Harness, MockIpfs};
+
+// An IPNS name (in the form of an ed25519 peer ID) to publish to
+const NAME: &'static str =
+    "12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN";
+
+fn remote_url() -> String {
+    format!("ipgrv::/ipns/{}", NAME)
+}
+
+#[test]
+fn push_then_clone() {
+    let ipfs = MockIpfs::start();
+    let h = Harness::new(&ipfs.multiaddr());
+    let origin = h.init_repo("origin");
+    h.commit(&origin, "README", "hello\n", "first");
+    let head = h.commit(&origin, "README", "hello again\n", "second");
+
+    h.git(&origin, &["push", "-q", &remote_url(), "master"]);
+
+    let root = ipfs.resolve(NAME).expect("name was not published");
+    assert!(ipfs.is_pinned(&root), "root {} is not pinned", root);
+
+    h.git(&h.path(""), &["clone", "-q", &remote_url(), "clone"]);
+    let clone = h.path("clone");
+    assert_eq!(h.rev_parse(&clone, "HEAD"), head);
+    assert_eq!(
+        fs::read_to_string(clone.join("README")).unwrap(),
+        "hello again\n"
+    );
+    h.git(&clone, &["fsck", "--no-progress"]);
+}
+
+#[test]
+fn fetch_after_push() {
+    let ipfs = MockIpfs::start();
+    let h = Harness::new(&ipfs.multiaddr());
+    let origin = h.init_repo("origin");
+    h.commit(&origin, "README", "hello\n", "first");
+    h.git(&origin, &["push", "-q", &remote_url(), "master"]);
+    h.git(&h.path(""), &["clone", "-q", &remote_url(), "clone"]);
+    let blocks = ipfs.block_count();
+
+    let head = h.commit(&origin, "NEWS", "news\n", "second");
+    h.git(&origin, &["push", "-q", &remote_url(), "master"]);
+    // a commit, a tree, a blob and the new root
+    assert_eq!(ipfs.block_count(), blocks + 4);
+
+    let clone = h.path("clone");
+    h.git(&clone, &["fetch", "-q", "origin"]);
+    assert_eq!(h.rev_parse(&clone, "origin/master"), head);
+    h.git(&clone, &["merge", "-q", "--ff-only", "origin/master"]);
+    assert_eq!(fs::read_to_string(clone.join("NEWS")).unwrap(), "news\n");
+}
+
+#[test]
+fn fetch_skips_packed_objects() {
+    let ipfs = MockIpfs::start();
+    let h = Harness::new(&ipfs.multiaddr());
+    let origin = h.init_repo("origin");
+    for i in 0..5 {
+        let contents = format!("{}\n", i);
+        h.commit(&origin, "README", &contents, "commit");
+    }
+    h.git(&origin, &["push", "-q", &remote_url(), "master"]);
+    h.git(&h.path(""), &["clone", "-q", &remote_url(), "clone"]);
+    let clone = h.path("clone");
+    h.git(&clone, &["gc", "-q"]);
+
+    let head = h.commit(&origin, "README", "new\n", "new");
+    h.git(&origin, &["push", "-q", &remote_url(), "master"]);
+    let block_gets = ipfs.block_gets();
+    h.git(&clone, &["fetch", "-q", "origin"]);
+
+    assert_eq!(h.rev_parse(&clone, "origin/master"), head);
+    // only the new commit, its tree and the new blob
+    assert_eq!(ipfs.block_gets(), block_gets + 3);
+}
+
+#[test]
+fn non_fast_forward_push_is_rejected() {
+    let ipfs = MockIpfs::start();
+    let h = Harness::new(&ipfs.multiaddr());
+    let origin = h.init_repo("origin");
+    h.commit(&origin, "README", "one\n", "first");
+    h.commit(&origin, "README", "two\n", "second");
+    h.git(&origin, &["push", "-q", &remote_url(), "master"]);
+    let published = ipfs.resolve(NAME);
+
+    h.git(&origin, &["reset", "-q", "--hard", "HEAD~1"]);
+    let rewritten = h.commit(&origin, "README", "three\n", "rewritten");
+    let stderr = h.git_fails(&origin, &["push", "-q", &remote_url(), "master"]);
+    assert!(stderr.contains("non-fast-forward"), "{}", stderr);
+    assert_eq!(ipfs.resolve(NAME), published);
+
+    h.git(&origin, &["push", "-q", "--force", &remote_url(), "master"]);
+    let refs = h.git(&origin, &["ls-remote", &remote_url()]);
+    assert!(
+        refs.contains(&format!("{}\trefs/heads/master", rewritten)),
+        "{}",
+        refs
+    );
+}
+
+#[test]
+fn clone_from_root_cid() {
+    let ipfs = MockIpfs::start();
+    let h = Harness::new(&ipfs.multiaddr());
+    let origin = h.init_repo("origin");
+    let head = h.commit(&origin, "README", "hello\n", "first");
+    h.git(&origin, &["push", "-q", &remote_url(), "master"]);
+    let root = ipfs.resolve(NAME).expect("name was not published");
+
+    let cid_url = format!("ipgrv::{}", root.trim_start_matches("/ipfs/"));
+    let path_url = format!("ipgrv::{}", root);
+    for &(url, dir) in &[(&cid_url, "by-cid"), (&path_url, "by-path")] {
+        h.git(&h.path(""), &["clone", "-q", url, dir]);
+        assert_eq!(h.rev_parse(&h.path(dir), "HEAD"), head);
+    }
+}
+
+#[test]
+fn push_and_clone_through_git_hash() {
+    let ipfs = MockIpfs::start();
+    let h = Harness::new(&ipfs.multiaddr());
+    let origin = h.init_repo("origin");
+    let first = h.commit(&origin, "README", "hello\n", "first");
+    let head = h.commit(&origin, "README", "hello again\n", "second");
+
+    // the remote starts out as the first commit, which gets pushed along
+    // with the second one
+    let url = format!("ipgrv::{}", first);
+    h.git(&origin, &["push", "-q", &url, "master"]);
+
+    let url = format!("ipgrv::{}", head);
+    h.git(&h.path(""), &["clone", "-q", &url, "clone"]);
+    let clone = h.path("clone");
+    assert_eq!(h.rev_parse(&clone, "HEAD"), head);
+    assert_eq!(
+        fs::read_to_string(clone.join("README")).unwrap(),
+        "hello again\n"
+    );
+    h.git(&clone, &["fsck", "--no-progress"]);
+}
+
+#[test]
+fn pushes_to_root_cid_build_on_each_other() {
+    let ipfs = MockIpfs::start();
+    let h = Harness::new(&ipfs.multiaddr());
+    let origin = h.init_repo("origin");
+    h.commit(&origin, "README", "hello\n", "first");
+    h.git(&origin, &["push", "-q", &remote_url(), "master"]);
+    let root = ipfs.resolve(NAME).expect("name was not published");
+    let url = format!("ipgrv::{}", root.trim_start_matches("/ipfs/"));
+    h.git(&origin, &["remote", "add", "cid", &url]);
+
+    h.git(&origin, &["branch", "topic"]);
+    h.git(&origin, &["push", "-q", "cid", "topic"]);
+    let head = h.commit(&origin, "README", "hello again\n", "second");
+    h.git(&origin, &["push", "-q", "cid", "master"]);
+
+    // the second push started from the root published by the first one
+    let refs = h.git(&origin, &["ls-remote", "cid"]);
+    assert!(refs.contains("\trefs/heads/topic"), "{}", refs);
+    assert!(
+        refs.contains(&format!("{}\trefs/heads/master", head)),
+        "{}",
+        refs
+    );
+}
+
+#[test]
+fn push_tags_and_delete_branches() {
+    let ipfs = MockIpfs::start();
+    let h = Harness::new(&ipfs.multiaddr());
+    let origin = h.init_repo("origin");
+    let head = h.commit(&origin, "README", "hello\n", "first");
+    h.git(&origin, &["tag", "-a", "-m", "release", "v1.0"]);
+    h.git(&origin, &["branch", "topic"]);
+
+    h.git(
+        &origin,
+        &["push", "-q", &remote_url(), "master", "topic", "v1.0"],
+    );
+    let refs = h.git(&origin, &["ls-remote", &remote_url()]);
+    assert!(refs.contains("\trefs/heads/topic"), "{}", refs);
+    assert!(
+        refs.contains(&format!("{}\trefs/tags/v1.0^{{}}", head)),
+        "{}",
+        refs
+    );
+
+    h.git(&origin, &["push", "-q", &remote_url(), ":topic"]);
+    let refs = h.git(&origin, &["ls-remote", &remote_url()]);
+    assert!(!refs.contains("\trefs/heads/topic"), "{}", refs);
+    assert!(refs.contains("\trefs/heads/master"), "{}", refs);
+}
+
+#[test]
+fn clone_of_unpublished_name_fails() {
+    let ipfs = MockIpfs::start();
+    let h = Harness::new(&ipfs.multiaddr());
+    let stderr =
+        h.git_fails(&h.path(""), &["clone", "-q", &remote_url(), "clone"]);
+    assert!(
+        stderr.contains("does not point to a repository"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn directory_store_round_trip() {
+    let mut h = Harness::new("");
+    let store = format!("file://{}", h.path("store").display());
+    h.set_api(&store);
+    let origin = h.init_repo("origin");
+    let head = h.commit(&origin, "README", "offline\n", "first");
+
+    h.git(&origin, &["push", "-q", &remote_url(), "master"]);
+    h.git(&h.path(""), &["clone", "-q", &remote_url(), "clone"]);
+    assert_eq!(h.rev_parse(&h.path("clone"), "HEAD"), head);
+}